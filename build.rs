@@ -15,6 +15,15 @@ macro_rules! get_command_output {
 fn main() {
     embuild::espidf::sysenv::output();
 
+    // the wake stub runs before the flash is available, so it must not call any functions
+    let stub = std::env::var_os("CARGO_FEATURE_ANEMOMETER").is_some()
+        || std::env::var_os("CARGO_FEATURE_RAIN_GAUGE").is_some();
+    if stub && std::env::var("OPT_LEVEL").as_deref() == Ok("0") {
+        panic!(
+            "The pulse counting wake stub requires an optimized build (`opt-level` other than 0)"
+        );
+    }
+
     let current_date_time = get_command_output!("date", "+%d.%m.%Y %H:%M:%S");
     let git_hash = get_command_output!("git", "rev-parse", "--short", "HEAD");
     let git_is_tagged = Command::new("git")
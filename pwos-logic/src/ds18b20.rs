@@ -0,0 +1,394 @@
+//! Driver for Maxim DS18B20 1-Wire temperature probes.
+//!
+//! Multiple probes can share the same bus. Each one is discovered using a ROM search
+//! and is addressed by its ROM code afterwards. Both externally powered and
+//! parasite-powered probes are supported.
+//!
+//! These sensors work over the 1-Wire protocol.
+
+use crate::onewire::{self, crc8, Error, OneWireBus, RomCode};
+use std::{thread::sleep, time::Duration};
+
+/// Maximum number of probes handled on a single bus.
+pub const MAX_PROBES: usize = 4;
+/// Length of the scratchpad, including the CRC byte.
+const SCRATCHPAD_LEN: usize = 9;
+/// Temperature register value after power-on, returned if no conversion has been done.
+const POWER_ON_VALUE: i16 = 0x0550;
+
+/// Temperature in °C.
+pub type Temperature = f32;
+
+/// Results of a measurement, by the ROM code of each probe.
+pub type Readings = heapless::Vec<(RomCode, Result<Temperature, Error>), MAX_PROBES>;
+
+/// Commands for DS18B20 sensors.
+#[derive(Clone, Copy)]
+enum Command {
+    /// Address all devices on the bus
+    SkipRom,
+
+    /// Address a single device by its ROM code
+    MatchRom,
+
+    /// Start a temperature conversion
+    ConvertTemperature,
+
+    /// Write the alarm and configuration registers
+    WriteScratchpad,
+
+    /// Read the full scratchpad
+    ReadScratchpad,
+
+    /// Ask whether any device is parasite-powered
+    ReadPowerSupply,
+}
+
+/// Measurement resolution.
+///
+/// Higher resolutions take longer to convert, see [`conversion_time()`](Self::conversion_time).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// 0.5°C steps
+    Bits9,
+
+    /// 0.25°C steps
+    Bits10,
+
+    /// 0.125°C steps
+    Bits11,
+
+    /// 0.0625°C steps
+    Bits12,
+}
+
+/// Driver handle for all DS18B20 probes on a 1-Wire bus.
+pub struct Ds18b20<B: OneWireBus> {
+    /// 1-Wire bus the probes are connected to.
+    bus: B,
+
+    /// ROM codes of the discovered probes.
+    probes: heapless::Vec<RomCode, MAX_PROBES>,
+
+    /// Whether any of the probes is parasite-powered.
+    parasitic: bool,
+
+    /// Currently configured resolution.
+    resolution: Resolution,
+}
+
+impl<B: OneWireBus> Ds18b20<B> {
+    /// Family code of the DS18B20.
+    pub const FAMILY_CODE: u8 = 0x28;
+
+    /// Initialize the driver, discover all probes on the bus and set their resolution.
+    ///
+    /// # Errors
+    /// Returns an error if no probe is present on the bus or the ROM search fails.
+    pub fn new(mut bus: B, resolution: Resolution) -> Result<Self, Error> {
+        log::debug!("Loading driver");
+
+        let probes = onewire::search(&mut bus, Some(Self::FAMILY_CODE))?;
+        if probes.is_empty() {
+            return Err(Error::NoDevice);
+        }
+
+        for probe in &probes {
+            log::debug!("Found probe {probe}");
+        }
+
+        let mut dev = Self {
+            bus,
+            probes,
+            parasitic: false,
+            resolution,
+        };
+
+        dev.parasitic = dev.read_power_supply()?;
+        if dev.parasitic {
+            log::debug!("Parasite-powered probe detected");
+        }
+
+        dev.set_resolution(resolution)?;
+
+        Ok(dev)
+    }
+
+    /// Write the resolution into the configuration register of all probes.
+    ///
+    /// The setting is not copied into the EEPROM, so it's lost once the probes lose power.
+    ///
+    /// # Errors
+    /// Returns an error if no probe answers the reset pulse.
+    pub fn set_resolution(&mut self, resolution: Resolution) -> Result<(), Error> {
+        // alarm registers are not used, so they're left at their default values
+        self.select(None)?;
+        self.bus.write_byte(Command::WriteScratchpad.as_byte());
+        self.bus
+            .write_bytes(&[0x4B, 0x46, resolution.config_register()]);
+        self.resolution = resolution;
+
+        Ok(())
+    }
+
+    /// Start a conversion on all probes at once and read the results.
+    ///
+    /// Invalid readings are returned as `Err(..)`, so that a single faulty probe does not
+    /// affect the others.
+    ///
+    /// # Errors
+    /// Returns an error if no probe answers the reset pulse.
+    pub fn measure(&mut self) -> Result<Readings, Error> {
+        self.select(None)?;
+        self.bus.write_byte(Command::ConvertTemperature.as_byte());
+
+        // Parasite-powered probes draw their power from the data line during the conversion.
+        // The pull-up resistor cannot supply enough current, so the line must be driven high.
+        if self.parasitic {
+            self.bus.set_strong_pullup(true);
+        }
+
+        sleep(self.resolution.conversion_time());
+
+        if self.parasitic {
+            self.bus.set_strong_pullup(false);
+        }
+
+        let mut results = heapless::Vec::new();
+
+        for i in 0..self.probes.len() {
+            let rom = self.probes[i];
+            let result = self.read_temperature(rom);
+
+            // SAFETY: The number of results is the same as the number of probes, which can't exceed `MAX_PROBES`.
+            unsafe { results.push((rom, result)).unwrap_unchecked() };
+        }
+
+        Ok(results)
+    }
+
+    /// Read the result of the last conversion from a single probe.
+    fn read_temperature(&mut self, rom: RomCode) -> Result<Temperature, Error> {
+        let mut scratchpad = [0u8; SCRATCHPAD_LEN];
+
+        self.select(Some(rom))?;
+        self.bus.write_byte(Command::ReadScratchpad.as_byte());
+        self.bus.read_bytes(&mut scratchpad);
+
+        decode_scratchpad(&scratchpad)
+    }
+
+    /// Returns whether any probe on the bus is parasite-powered.
+    ///
+    /// Parasite-powered probes pull the bus low during the read time slot.
+    fn read_power_supply(&mut self) -> Result<bool, Error> {
+        self.select(None)?;
+        self.bus.write_byte(Command::ReadPowerSupply.as_byte());
+
+        Ok(!self.bus.read_bit())
+    }
+
+    /// Reset the bus and address either a single probe or all of them.
+    fn select(&mut self, rom: Option<RomCode>) -> Result<(), Error> {
+        if !self.bus.reset() {
+            return Err(Error::NoDevice);
+        }
+
+        match rom {
+            Some(rom) => {
+                self.bus.write_byte(Command::MatchRom.as_byte());
+                self.bus.write_bytes(&rom.0);
+            }
+            None => self.bus.write_byte(Command::SkipRom.as_byte()),
+        }
+
+        Ok(())
+    }
+}
+
+/// Decode the temperature from a scratchpad.
+///
+/// # Errors
+/// Returns [`Error::BusLow`] if the scratchpad only contains zeros, [`Error::Crc`] if its CRC is
+/// invalid or [`Error::PowerOnValue`] if the probe has not done a conversion since it was powered on.
+fn decode_scratchpad(scratchpad: &[u8; SCRATCHPAD_LEN]) -> Result<Temperature, Error> {
+    // the CRC of all zeros is zero as well, so this would pass the CRC check
+    if scratchpad.iter().all(|byte| *byte == 0) {
+        return Err(Error::BusLow);
+    }
+
+    if crc8(scratchpad) != 0 {
+        return Err(Error::Crc);
+    }
+
+    let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]);
+
+    // 85°C is a valid reading in theory, but it's far more likely that the conversion never ran
+    if raw == POWER_ON_VALUE {
+        return Err(Error::PowerOnValue);
+    }
+
+    Ok(f32::from(raw) / 16.0)
+}
+
+impl Resolution {
+    /// Get the value of the configuration register for this resolution.
+    const fn config_register(self) -> u8 {
+        match self {
+            Self::Bits9 => 0x1F,
+            Self::Bits10 => 0x3F,
+            Self::Bits11 => 0x5F,
+            Self::Bits12 => 0x7F,
+        }
+    }
+
+    /// Maximum conversion time as given in the datasheet.
+    pub const fn conversion_time(self) -> Duration {
+        match self {
+            Self::Bits9 => Duration::from_micros(93_750),
+            Self::Bits10 => Duration::from_micros(187_500),
+            Self::Bits11 => Duration::from_millis(375),
+            Self::Bits12 => Duration::from_millis(750),
+        }
+    }
+}
+
+impl Command {
+    /// Get the command as a byte for 1-Wire transmission.
+    const fn as_byte(self) -> u8 {
+        match self {
+            Self::SkipRom => 0xCC,
+            Self::MatchRom => 0x55,
+            Self::ConvertTemperature => 0x44,
+            Self::WriteScratchpad => 0x4E,
+            Self::ReadScratchpad => 0xBE,
+            Self::ReadPowerSupply => 0xB4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_scratchpad, Ds18b20, Resolution, SCRATCHPAD_LEN};
+    use crate::onewire::{
+        crc8,
+        sim::{rom, SimBus, SimDevice},
+        Error,
+    };
+
+    /// Build a scratchpad with a valid CRC.
+    fn scratchpad(raw: i16) -> [u8; SCRATCHPAD_LEN] {
+        let [lsb, msb] = raw.to_le_bytes();
+        let mut scratchpad = [lsb, msb, 0x4B, 0x46, 0x7F, 0xFF, 0x0C, 0x10, 0x00];
+        scratchpad[8] = crc8(&scratchpad[..8]);
+
+        scratchpad
+    }
+
+    #[test]
+    fn decode_temperatures() {
+        assert_eq!(decode_scratchpad(&scratchpad(0x0191)), Ok(25.0625));
+        assert_eq!(decode_scratchpad(&scratchpad(0x0000)), Ok(0.0));
+        assert_eq!(decode_scratchpad(&scratchpad(-0x00A2)), Ok(-10.125));
+        assert_eq!(decode_scratchpad(&scratchpad(-0x0370)), Ok(-55.0));
+    }
+
+    #[test]
+    fn decode_rejects_bad_crc() {
+        let mut data = scratchpad(0x0191);
+        data[0] ^= 0x01;
+
+        assert_eq!(decode_scratchpad(&data), Err(Error::Crc));
+    }
+
+    #[test]
+    fn decode_rejects_all_zeros() {
+        assert_eq!(decode_scratchpad(&[0; SCRATCHPAD_LEN]), Err(Error::BusLow));
+    }
+
+    #[test]
+    fn decode_rejects_power_on_value() {
+        assert_eq!(
+            decode_scratchpad(&scratchpad(0x0550)),
+            Err(Error::PowerOnValue)
+        );
+    }
+
+    #[test]
+    fn resolution_is_written_to_all_probes() {
+        let bus = SimBus::new(vec![
+            SimDevice::new(Ds18b20::<SimBus>::FAMILY_CODE, 1, 0),
+            SimDevice::new(Ds18b20::<SimBus>::FAMILY_CODE, 2, 0),
+        ]);
+
+        let dev = Ds18b20::new(bus, Resolution::Bits10).unwrap();
+
+        for device in &dev.bus.devices {
+            assert_eq!(device.scratchpad[2..5], [0x4B, 0x46, 0x3F]);
+        }
+    }
+
+    #[test]
+    fn resolution_bytes() {
+        assert_eq!(Resolution::Bits9.config_register(), 0x1F);
+        assert_eq!(Resolution::Bits10.config_register(), 0x3F);
+        assert_eq!(Resolution::Bits11.config_register(), 0x5F);
+        assert_eq!(Resolution::Bits12.config_register(), 0x7F);
+    }
+
+    #[test]
+    fn measure_multiple_probes() {
+        let bus = SimBus::new(vec![
+            SimDevice::new(Ds18b20::<SimBus>::FAMILY_CODE, 1, 0x0191),
+            SimDevice::new(0x10, 2, 0x0000),
+            SimDevice::new(Ds18b20::<SimBus>::FAMILY_CODE, 3, -0x00A2),
+        ]);
+
+        let mut dev = Ds18b20::new(bus, Resolution::Bits9).unwrap();
+        let results = dev.measure().unwrap();
+
+        assert_eq!(dev.bus.conversions, 1);
+        assert_eq!(
+            results.as_slice(),
+            &[
+                (rom(Ds18b20::<SimBus>::FAMILY_CODE, 1), Ok(25.0625)),
+                (rom(Ds18b20::<SimBus>::FAMILY_CODE, 3), Ok(-10.125)),
+            ]
+        );
+    }
+
+    #[test]
+    fn externally_powered_probes_use_no_strong_pullup() {
+        let bus = SimBus::new(vec![SimDevice::new(Ds18b20::<SimBus>::FAMILY_CODE, 1, 0)]);
+
+        let mut dev = Ds18b20::new(bus, Resolution::Bits9).unwrap();
+        dev.measure().unwrap();
+
+        assert!(!dev.parasitic);
+        assert!(dev.bus.strong_pullup.is_empty());
+    }
+
+    #[test]
+    fn parasite_powered_probe_gets_strong_pullup() {
+        let bus = SimBus::new(vec![
+            SimDevice::new(Ds18b20::<SimBus>::FAMILY_CODE, 1, 0),
+            SimDevice::new(Ds18b20::<SimBus>::FAMILY_CODE, 2, 0).parasitic(),
+        ]);
+
+        let mut dev = Ds18b20::new(bus, Resolution::Bits9).unwrap();
+        dev.measure().unwrap();
+
+        assert!(dev.parasitic);
+        assert_eq!(dev.bus.strong_pullup, [true, false]);
+    }
+
+    #[test]
+    fn no_probes_on_bus() {
+        let bus = SimBus::new(vec![SimDevice::new(0x10, 1, 0)]);
+
+        assert!(matches!(
+            Ds18b20::new(bus, Resolution::Bits9),
+            Err(Error::NoDevice)
+        ));
+    }
+}
@@ -0,0 +1,11 @@
+//! Hardware-independent parts of PixelWeatherOS.
+//!
+//! This crate does not depend on ESP-IDF, so it's built for the host as well, where the protocol and
+//! conversion logic is tested (`cargo test` in this directory). The firmware uses it as a regular dependency.
+
+#![warn(clippy::unwrap_used)]
+#![deny(unused_must_use)]
+
+pub mod ds18b20;
+pub mod onewire;
+pub mod pulse;
@@ -0,0 +1,331 @@
+//! 1-Wire protocol layer.
+//!
+//! The protocol layer ([`OneWireBus`], [`search()`], [`crc8()`]) is independent of the hardware,
+//! so it can be driven by anything that can produce the reset, write and read time slots.
+//! The firmware implements the bus on a GPIO pin, the tests use a [simulated bus](sim).
+
+#[cfg(test)]
+pub mod sim;
+
+use std::{cmp::Ordering, fmt::Display};
+use thiserror::Error;
+
+/// ROM command for enumerating devices on the bus.
+const CMD_SEARCH_ROM: u8 = 0xF0;
+
+/// Errors of the 1-Wire protocol layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum Error {
+    /// No device answered the reset pulse.
+    #[error("No device on the 1-Wire bus")]
+    NoDevice,
+
+    /// Received data has an invalid CRC.
+    #[error("1-Wire CRC mismatch")]
+    Crc,
+
+    /// Only zeros were received, which is what a bus that is held low reads as.
+    #[error("1-Wire bus is held low")]
+    BusLow,
+
+    /// A device has returned its power-on default instead of a result.
+    #[error("1-Wire device returned its power-on value")]
+    PowerOnValue,
+}
+
+/// A 64-bit device ROM code (family code, serial number and CRC).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomCode(pub [u8; 8]);
+
+/// Low-level operations a 1-Wire bus master must support.
+///
+/// All multi-bit operations are built on top of the single-bit time slots,
+/// LSB first, as required by the protocol.
+pub trait OneWireBus {
+    /// Send a reset pulse.
+    ///
+    /// Returns whether at least one device answered with a presence pulse.
+    fn reset(&mut self) -> bool;
+
+    /// Generate a single write time slot.
+    fn write_bit(&mut self, bit: bool);
+
+    /// Generate a single read time slot and sample the bus.
+    fn read_bit(&mut self) -> bool;
+
+    /// Actively drive the bus high to supply parasite-powered devices during
+    /// power-hungry operations, such as a temperature conversion.
+    fn set_strong_pullup(&mut self, enable: bool);
+
+    /// Write a full byte.
+    fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit(byte & (1 << i) != 0);
+        }
+    }
+
+    /// Read a full byte.
+    fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |acc, i| acc | (u8::from(self.read_bit()) << i))
+    }
+
+    /// Write all bytes from the buffer.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_byte(*byte);
+        }
+    }
+
+    /// Fill the buffer with bytes read from the bus.
+    fn read_bytes(&mut self, buffer: &mut [u8]) {
+        for byte in buffer {
+            *byte = self.read_byte();
+        }
+    }
+}
+
+/// Enumerate the ROM codes of all devices on the bus.
+///
+/// If `family` is set, only devices with the matching family code are returned.
+/// At most `N` devices are returned, any additional devices are ignored.
+///
+/// This implements the search algorithm described in Maxim's application note 187.
+///
+/// # Errors
+/// Returns [`Error::NoDevice`] if no device answered the reset pulse, [`Error::BusLow`] if the
+/// bus is held low or [`Error::Crc`] if a discovered ROM code is corrupted.
+pub fn search<B: OneWireBus, const N: usize>(
+    bus: &mut B,
+    family: Option<u8>,
+) -> Result<heapless::Vec<RomCode, N>, Error> {
+    let mut found = heapless::Vec::new();
+    let mut rom = [0u8; 8];
+    let mut last_discrepancy = 0;
+
+    loop {
+        if !bus.reset() {
+            return Err(Error::NoDevice);
+        }
+
+        bus.write_byte(CMD_SEARCH_ROM);
+
+        let mut discrepancy = 0;
+
+        for bit_number in 1..=64 {
+            let byte = (bit_number - 1) / 8;
+            let mask = 1 << ((bit_number - 1) % 8);
+
+            let bit = bus.read_bit();
+            let complement = bus.read_bit();
+
+            let direction = if bit && complement {
+                // no device participates anymore
+                return Err(Error::NoDevice);
+            } else if bit != complement {
+                // all remaining devices have the same bit here
+                bit
+            } else {
+                // conflict, choose the direction based on the previous pass
+                let direction = match bit_number.cmp(&last_discrepancy) {
+                    Ordering::Less => rom[byte] & mask != 0,
+                    Ordering::Equal => true,
+                    Ordering::Greater => false,
+                };
+
+                if !direction {
+                    discrepancy = bit_number;
+                }
+
+                direction
+            };
+
+            if direction {
+                rom[byte] |= mask;
+            } else {
+                rom[byte] &= !mask;
+            }
+
+            bus.write_bit(direction);
+        }
+
+        let code = RomCode(rom);
+        if code.0 == [0; 8] {
+            // the CRC of all zeros is zero as well, so this would pass the CRC check
+            return Err(Error::BusLow);
+        }
+        if !code.valid() {
+            return Err(Error::Crc);
+        }
+
+        if family.is_none_or(|family| family == code.family()) && found.push(code).is_err() {
+            log::warn!("Too many 1-Wire devices, ignoring the rest");
+            break;
+        }
+
+        if discrepancy == 0 {
+            break;
+        }
+
+        last_discrepancy = discrepancy;
+    }
+
+    Ok(found)
+}
+
+/// Calculate the Dallas/Maxim CRC-8 checksum (polynomial `x^8 + x^5 + x^4 + 1`).
+///
+/// Running this over data including its trailing CRC byte yields `0`.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, byte| {
+        let mut byte = *byte;
+
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 0x01;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            byte >>= 1;
+        }
+
+        crc
+    })
+}
+
+impl RomCode {
+    /// Returns the family code of the device.
+    pub const fn family(self) -> u8 {
+        self.0[0]
+    }
+
+    /// Returns whether the CRC of the ROM code is valid.
+    pub fn valid(self) -> bool {
+        crc8(&self.0) == 0
+    }
+}
+
+impl Display for RomCode {
+    /// Formats the ROM code as hex, in the order it's usually printed on labels (MSB first).
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0.iter().rev() {
+            write!(f, "{byte:02X}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        crc8, search,
+        sim::{rom, SimBus, SimDevice},
+        Error, RomCode,
+    };
+
+    #[test]
+    fn crc_of_known_rom_code() {
+        // example from Maxim's application note 27
+        let rom = [0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2];
+
+        assert_eq!(crc8(&rom[..7]), 0xA2);
+        assert_eq!(crc8(&rom), 0);
+        assert!(RomCode(rom).valid());
+    }
+
+    #[test]
+    fn crc_detects_corruption() {
+        let mut rom = rom(0x28, 0x1234_5678_9ABC).0;
+        rom[3] ^= 0x10;
+
+        assert!(!RomCode(rom).valid());
+    }
+
+    #[test]
+    fn crc_of_empty_data() {
+        assert_eq!(crc8(&[]), 0);
+    }
+
+    #[test]
+    fn search_single_device() {
+        let mut bus = SimBus::new(vec![SimDevice::new(0x28, 0xAB_CDEF, 0)]);
+
+        let found = search::<_, 4>(&mut bus, None).unwrap();
+
+        assert_eq!(found.as_slice(), &[rom(0x28, 0xAB_CDEF)]);
+    }
+
+    #[test]
+    fn search_branches_over_all_devices() {
+        // the serials differ in the lowest bit, a middle bit and the highest bit,
+        // so the search has to branch at several positions
+        let serials = [
+            0x0000_0000_0001,
+            0x0000_0000_0000,
+            0x0000_0100_0000,
+            0x8000_0000_0000,
+        ];
+        let mut bus = SimBus::new(
+            serials
+                .iter()
+                .map(|serial| SimDevice::new(0x28, *serial, 0))
+                .collect(),
+        );
+
+        let found = search::<_, 8>(&mut bus, None).unwrap();
+
+        assert_eq!(found.len(), serials.len());
+        for serial in serials {
+            assert!(found.contains(&rom(0x28, serial)));
+        }
+    }
+
+    #[test]
+    fn search_filters_family() {
+        let mut bus = SimBus::new(vec![
+            SimDevice::new(0x28, 1, 0),
+            SimDevice::new(0x10, 2, 0),
+            SimDevice::new(0x28, 3, 0),
+        ]);
+
+        let found = search::<_, 4>(&mut bus, Some(0x28)).unwrap();
+
+        assert_eq!(found.as_slice(), &[rom(0x28, 1), rom(0x28, 3)]);
+    }
+
+    #[test]
+    fn search_stops_at_capacity() {
+        let mut bus = SimBus::new(
+            (1..=5)
+                .map(|serial| SimDevice::new(0x28, serial, 0))
+                .collect(),
+        );
+
+        let found = search::<_, 2>(&mut bus, None).unwrap();
+
+        assert_eq!(found.len(), 2);
+    }
+
+    #[test]
+    fn search_empty_bus() {
+        let mut bus = SimBus::new(Vec::new());
+
+        assert_eq!(search::<_, 4>(&mut bus, None), Err(Error::NoDevice));
+    }
+
+    #[test]
+    fn search_bus_held_low() {
+        let mut bus = SimBus::new(Vec::new());
+        bus.held_low = true;
+
+        assert_eq!(search::<_, 4>(&mut bus, None), Err(Error::BusLow));
+    }
+
+    #[test]
+    fn rom_code_display() {
+        assert_eq!(
+            RomCode([0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2]).to_string(),
+            "A200000001B81C02"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Simulated 1-Wire bus for testing the protocol layer and the drivers built on it.
+//!
+//! The devices behave like DS18B20 probes. They understand the ROM commands, the search
+//! algorithm and the commands used by the driver. Like on a real open-drain bus, a read
+//! time slot returns the wired-AND of all devices that are driving the bus.
+
+use super::{crc8, OneWireBus, RomCode};
+
+/// A simulated device on the bus.
+pub struct SimDevice {
+    /// ROM code of the device.
+    pub rom: RomCode,
+
+    /// Scratchpad contents, including the CRC byte.
+    pub scratchpad: [u8; 9],
+
+    /// Raw temperature the device reports after a conversion.
+    pub raw_temperature: i16,
+
+    /// Whether the device is parasite-powered.
+    pub parasitic: bool,
+}
+
+/// A simulated bus with any number of devices.
+#[derive(Default)]
+pub struct SimBus {
+    /// Devices connected to the bus.
+    pub devices: Vec<SimDevice>,
+
+    /// Whether the bus is shorted to ground.
+    pub held_low: bool,
+
+    /// Every call to [`OneWireBus::set_strong_pullup`], in order.
+    pub strong_pullup: Vec<bool>,
+
+    /// Number of conversions started.
+    pub conversions: usize,
+
+    /// Protocol state.
+    state: State,
+
+    /// Bits of the byte currently being written.
+    byte: u8,
+
+    /// Number of bits in `byte`.
+    bit_count: u8,
+}
+
+/// Protocol state of the simulated devices.
+#[derive(Default)]
+enum State {
+    /// Waiting for a reset pulse.
+    #[default]
+    Idle,
+
+    /// Waiting for a ROM command.
+    RomCommand,
+
+    /// Performing a ROM search.
+    Search {
+        /// Devices that still participate.
+        active: Vec<bool>,
+
+        /// Current bit position.
+        bit: usize,
+
+        /// Number of read slots done at the current position.
+        reads: u8,
+    },
+
+    /// Receiving the ROM code after a Match ROM command.
+    MatchRom(Vec<u8>),
+
+    /// Waiting for a function command, with the addressed devices.
+    Function(Vec<usize>),
+
+    /// Receiving the alarm and configuration registers.
+    WriteScratchpad(Vec<usize>, Vec<u8>),
+
+    /// Sending data to the master, LSB first.
+    Reply(Vec<bool>),
+
+    /// Reporting the power supply of the addressed devices.
+    PowerSupply(Vec<usize>),
+}
+
+impl SimDevice {
+    /// Create an externally powered device with a valid ROM code.
+    pub fn new(family: u8, serial: u64, raw_temperature: i16) -> Self {
+        let mut scratchpad = [0x50, 0x05, 0x4B, 0x46, 0x7F, 0xFF, 0x0C, 0x10, 0x00];
+        scratchpad[8] = crc8(&scratchpad[..8]);
+
+        Self {
+            rom: rom(family, serial),
+            scratchpad,
+            raw_temperature,
+            parasitic: false,
+        }
+    }
+
+    /// Make the device parasite-powered.
+    pub const fn parasitic(mut self) -> Self {
+        self.parasitic = true;
+        self
+    }
+
+    /// Recalculate the CRC of the scratchpad.
+    fn update_crc(&mut self) {
+        self.scratchpad[8] = crc8(&self.scratchpad[..8]);
+    }
+}
+
+impl SimBus {
+    /// Create a bus with the given devices.
+    pub fn new(devices: Vec<SimDevice>) -> Self {
+        Self {
+            devices,
+            ..Self::default()
+        }
+    }
+
+    /// Handle a fully received byte.
+    fn receive(&mut self, byte: u8) {
+        self.state = match std::mem::take(&mut self.state) {
+            State::RomCommand => match byte {
+                0xF0 => State::Search {
+                    active: vec![true; self.devices.len()],
+                    bit: 0,
+                    reads: 0,
+                },
+                0x55 => State::MatchRom(Vec::new()),
+                0xCC => State::Function((0..self.devices.len()).collect()),
+                _ => State::Idle,
+            },
+            State::MatchRom(mut rom) => {
+                rom.push(byte);
+
+                if rom.len() < 8 {
+                    State::MatchRom(rom)
+                } else {
+                    State::Function(
+                        (0..self.devices.len())
+                            .filter(|i| self.devices[*i].rom.0[..] == rom[..])
+                            .collect(),
+                    )
+                }
+            }
+            State::Function(selected) => match byte {
+                0x44 => {
+                    self.conversions += 1;
+
+                    for i in selected {
+                        let device = &mut self.devices[i];
+                        device.scratchpad[..2]
+                            .copy_from_slice(&device.raw_temperature.to_le_bytes());
+                        device.update_crc();
+                    }
+
+                    State::Idle
+                }
+                0x4E => State::WriteScratchpad(selected, Vec::new()),
+                0xBE if selected.len() == 1 => State::Reply(
+                    self.devices[selected[0]]
+                        .scratchpad
+                        .iter()
+                        .flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0))
+                        .collect(),
+                ),
+                0xB4 => State::PowerSupply(selected),
+                _ => State::Idle,
+            },
+            State::WriteScratchpad(selected, mut data) => {
+                data.push(byte);
+
+                if data.len() < 3 {
+                    State::WriteScratchpad(selected, data)
+                } else {
+                    for i in selected {
+                        let device = &mut self.devices[i];
+                        device.scratchpad[2..5].copy_from_slice(&data);
+                        device.update_crc();
+                    }
+
+                    State::Idle
+                }
+            }
+            state => state,
+        };
+    }
+}
+
+impl OneWireBus for SimBus {
+    fn reset(&mut self) -> bool {
+        self.state = State::RomCommand;
+        self.byte = 0;
+        self.bit_count = 0;
+
+        self.held_low || !self.devices.is_empty()
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        if let State::Search {
+            active,
+            bit: position,
+            reads,
+        } = &mut self.state
+        {
+            for (i, device) in self.devices.iter().enumerate() {
+                if device.rom.bit(*position) != bit {
+                    active[i] = false;
+                }
+            }
+
+            *position += 1;
+            *reads = 0;
+
+            if *position == 64 {
+                self.state = State::Idle;
+            }
+
+            return;
+        }
+
+        self.byte |= u8::from(bit) << self.bit_count;
+        self.bit_count += 1;
+
+        if self.bit_count == 8 {
+            let byte = self.byte;
+            self.byte = 0;
+            self.bit_count = 0;
+            self.receive(byte);
+        }
+    }
+
+    fn read_bit(&mut self) -> bool {
+        if self.held_low {
+            return false;
+        }
+
+        match &mut self.state {
+            State::Search {
+                active,
+                bit: position,
+                reads,
+            } => {
+                // first slot: the bit itself, second slot: its complement
+                let expected = *reads == 0;
+                *reads += 1;
+
+                self.devices
+                    .iter()
+                    .zip(active.iter())
+                    .filter(|(_, active)| **active)
+                    .all(|(device, _)| device.rom.bit(*position) == expected)
+            }
+            State::Reply(bits) if !bits.is_empty() => bits.remove(0),
+            State::PowerSupply(selected) => selected.iter().all(|i| !self.devices[*i].parasitic),
+            _ => true,
+        }
+    }
+
+    fn set_strong_pullup(&mut self, enable: bool) {
+        self.strong_pullup.push(enable);
+    }
+}
+
+impl RomCode {
+    /// Get a single bit of the ROM code, in transmission order.
+    const fn bit(self, position: usize) -> bool {
+        self.0[position / 8] & (1 << (position % 8)) != 0
+    }
+}
+
+/// Build a valid ROM code from a family code and a 48-bit serial number.
+pub fn rom(family: u8, serial: u64) -> RomCode {
+    let mut rom = [0; 8];
+    rom[0] = family;
+    rom[1..7].copy_from_slice(&serial.to_le_bytes()[..6]);
+    rom[7] = crc8(&rom[..7]);
+
+    RomCode(rom)
+}
@@ -0,0 +1,253 @@
+//! Pulse counters and the conversion of their counts into measurements.
+
+use std::time::Duration;
+
+/// Pulses recorded on a single input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counter {
+    /// Number of pulses since the last report.
+    pub count: u32,
+
+    /// Time of the last pulse in microseconds.
+    pub last_us: Option<u64>,
+
+    /// Shortest interval between two pulses since the last report.
+    pub min_interval_us: Option<u64>,
+}
+
+impl Counter {
+    /// Create a counter without any pulses.
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            last_us: None,
+            min_interval_us: None,
+        }
+    }
+
+    /// Record a pulse at `now_us`, unless it's contact bounce.
+    ///
+    /// If the clock has jumped back since the last pulse (e.g. after a time synchronization),
+    /// the pulse is counted, but the interval is not known.
+    pub fn record(&mut self, now_us: u64, debounce: Duration) {
+        if is_bounce(self.last_us, now_us, debounce) {
+            return;
+        }
+
+        if let Some(interval) = self.last_us.and_then(|last_us| now_us.checked_sub(last_us)) {
+            self.min_interval_us = Some(
+                self.min_interval_us
+                    .map_or(interval, |min| min.min(interval)),
+            );
+        }
+
+        self.count = self.count.saturating_add(1);
+        self.last_us = Some(now_us);
+    }
+
+    /// Add `count` pulses with the shortest interval `min_interval_us`, that were recorded elsewhere.
+    ///
+    /// Intervals between a pulse recorded elsewhere and one recorded by this counter are not known.
+    pub fn merge(self, count: u32, min_interval_us: Option<u64>) -> Self {
+        Self {
+            count: self.count.saturating_add(count),
+            min_interval_us: match (self.min_interval_us, min_interval_us) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            ..self
+        }
+    }
+}
+
+/// Returns whether a pulse is contact bounce and should be ignored.
+///
+/// A pulse is a bounce if it came less than `debounce` after the previous one.
+/// If the clock has jumped back since the previous pulse, it's not a bounce.
+pub const fn is_bounce(last_us: Option<u64>, now_us: u64, debounce: Duration) -> bool {
+    match last_us {
+        Some(last_us) if now_us >= last_us => ((now_us - last_us) as u128) < debounce.as_micros(),
+        _ => false,
+    }
+}
+
+/// Returns the length of the counting period, that has started at `start_us` and ends at `now_us`.
+///
+/// Returns `None` if the start is not known, it's after the end or the period is longer than `max`,
+/// since the counts cannot be converted into a meaningful average then.
+pub fn period(start_us: Option<u64>, now_us: u64, max: Duration) -> Option<Duration> {
+    let period = Duration::from_micros(now_us.checked_sub(start_us?)?);
+
+    (period <= max).then_some(period)
+}
+
+/// Calculate the average wind speed from the number of pulses counted during `period`.
+///
+/// `factor` is the wind speed in km/h that corresponds to one pulse per second.
+#[allow(clippy::cast_precision_loss)]
+pub fn wind_speed(count: u32, period: Duration, factor: f32) -> f32 {
+    if period.is_zero() {
+        return 0.0;
+    }
+
+    count as f32 / period.as_secs_f32() * factor
+}
+
+/// Calculate the wind gust speed from the shortest interval between two pulses.
+///
+/// `factor` is the wind speed in km/h that corresponds to one pulse per second.
+pub fn gust(min_interval: Duration, factor: f32) -> f32 {
+    if min_interval.is_zero() {
+        return 0.0;
+    }
+
+    factor / min_interval.as_secs_f32()
+}
+
+/// Calculate the rainfall in mm from the number of bucket tips.
+#[allow(clippy::cast_precision_loss)]
+pub fn rainfall(count: u32, mm_per_tip: f32) -> f32 {
+    count as f32 * mm_per_tip
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gust, is_bounce, period, rainfall, wind_speed, Counter};
+    use std::time::Duration;
+
+    const DEBOUNCE: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn first_pulse_is_not_bounce() {
+        assert!(!is_bounce(None, 0, DEBOUNCE));
+        assert!(!is_bounce(None, 5, DEBOUNCE));
+    }
+
+    #[test]
+    fn bounce_edges() {
+        assert!(is_bounce(Some(1_000), 1_000, DEBOUNCE));
+        assert!(is_bounce(Some(1_000), 10_999, DEBOUNCE));
+        assert!(!is_bounce(Some(1_000), 11_000, DEBOUNCE));
+        assert!(!is_bounce(Some(1_000), 11_001, DEBOUNCE));
+    }
+
+    #[test]
+    fn zero_debounce_accepts_everything() {
+        assert!(!is_bounce(Some(1_000), 1_000, Duration::ZERO));
+    }
+
+    #[test]
+    fn clock_jumping_back_is_not_bounce() {
+        assert!(!is_bounce(Some(1_000_000), 1_000, DEBOUNCE));
+    }
+
+    #[test]
+    fn record_ignores_bounces() {
+        let mut counter = Counter::new();
+
+        counter.record(0, DEBOUNCE);
+        counter.record(5_000, DEBOUNCE);
+        counter.record(20_000, DEBOUNCE);
+        counter.record(25_000, DEBOUNCE);
+        counter.record(50_000, DEBOUNCE);
+
+        assert_eq!(counter.count, 3);
+        assert_eq!(counter.last_us, Some(50_000));
+        assert_eq!(counter.min_interval_us, Some(20_000));
+    }
+
+    #[test]
+    fn record_after_clock_jump() {
+        let mut counter = Counter::new();
+
+        counter.record(1_000_000, DEBOUNCE);
+        counter.record(2_000_000, DEBOUNCE);
+        counter.record(500, DEBOUNCE);
+
+        assert_eq!(counter.count, 3);
+        assert_eq!(counter.last_us, Some(500));
+        assert_eq!(counter.min_interval_us, Some(1_000_000));
+    }
+
+    #[test]
+    fn count_does_not_wrap() {
+        let mut counter = Counter {
+            count: u32::MAX,
+            ..Counter::new()
+        };
+
+        counter.record(0, DEBOUNCE);
+        assert_eq!(counter.count, u32::MAX);
+
+        let merged = Counter {
+            count: u32::MAX - 1,
+            ..Counter::new()
+        }
+        .merge(5, None);
+        assert_eq!(merged.count, u32::MAX);
+    }
+
+    #[test]
+    fn merge_keeps_shortest_interval() {
+        let counter = Counter {
+            count: 2,
+            last_us: Some(100),
+            min_interval_us: Some(40),
+        };
+
+        assert_eq!(counter.merge(3, Some(30)).min_interval_us, Some(30));
+        assert_eq!(counter.merge(3, Some(50)).min_interval_us, Some(40));
+        assert_eq!(counter.merge(3, None).min_interval_us, Some(40));
+        assert_eq!(Counter::new().merge(3, Some(50)).min_interval_us, Some(50));
+        assert_eq!(counter.merge(3, None).count, 5);
+        assert_eq!(counter.merge(3, None).last_us, Some(100));
+    }
+
+    #[test]
+    fn period_length() {
+        let max = Duration::from_secs(60);
+
+        assert_eq!(
+            period(Some(1_000), 31_001_000, max),
+            Some(Duration::from_secs(31))
+        );
+        assert_eq!(period(Some(1_000), 60_001_000, max), Some(max));
+        assert_eq!(period(Some(1_000), 1_000, max), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn implausible_periods() {
+        let max = Duration::from_secs(60);
+
+        assert_eq!(period(None, 1_000, max), None);
+        assert_eq!(period(Some(2_000), 1_000, max), None);
+        assert_eq!(period(Some(0), 60_000_001, max), None);
+    }
+
+    #[test]
+    fn wind_speed_average() {
+        assert!((wind_speed(120, Duration::from_secs(60), 2.4) - 4.8).abs() < 1e-4);
+        assert!(wind_speed(0, Duration::from_secs(60), 2.4).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn wind_speed_zero_period() {
+        assert!(wind_speed(10, Duration::ZERO, 2.4).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn gust_from_shortest_interval() {
+        assert!((gust(Duration::from_millis(250), 2.4) - 9.6).abs() < 1e-4);
+    }
+
+    #[test]
+    fn gust_zero_interval() {
+        assert!(gust(Duration::ZERO, 2.4).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn rainfall_from_tips() {
+        assert!((rainfall(10, 0.2794) - 2.794).abs() < 1e-4);
+        assert!(rainfall(0, 0.2794).abs() < f32::EPSILON);
+    }
+}
@@ -1,18 +1,66 @@
+#[cfg(feature = "scd4x")]
+use crate::config::{ALTITUDE, CO2_SELF_CALIBRATION};
+#[cfg(feature = "solar")]
+use crate::sysc::charger::{self, ChargeState, Charger};
+#[cfg(feature = "pms5003")]
+use crate::sysc::ext_drivers::Pms5003 as PmSensor;
+#[cfg(feature = "scd4x")]
+use crate::sysc::ext_drivers::Scd4x;
+#[cfg(feature = "sds011")]
+use crate::sysc::ext_drivers::Sds011 as PmSensor;
+#[cfg(feature = "ds18b20")]
+use crate::sysc::ext_drivers::{onewire::PinOneWire, Ds18b20, Ds18b20Resolution};
+#[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+use crate::sysc::pulse;
+#[cfg(feature = "as3935")]
+use crate::sysc::{ext_drivers::As3935, lightning};
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
 use crate::{
-    config::{PWMP_SERVER, WIFI_NETWORKS, WIFI_TIMEOUT},
+    config::PM_SENSOR_INTERVAL,
+    sysc::{
+        ext_drivers::ParticulateSensor,
+        periph::UartPeripherals,
+        schedule::{self, Warmup},
+    },
+};
+use crate::{
+    config::{
+        BROWNOUT_SETTLE_TIME, BROWNOUT_TX_POWER, PWMP_SERVER, WAKE_ALIGNMENT, WIFI_NETWORKS,
+        WIFI_TIMEOUT,
+    },
     re_esp,
     sysc::{
-        battery::{Battery, CRITICAL_VOLTAGE},
-        ext_drivers::{AnySensor, BoschME280, EnvironmentSensor, Htu, MeasurementResults},
+        battery::{
+            self,
+            policy::{self, PowerTier},
+            source::{AnyBatterySource, BatterySource, FuelGauge},
+            Battery, BatteryChannel,
+        },
+        brownout,
+        channels::Channels,
+        clock,
+        ext_drivers::{
+            AnySensor, BoschME280, EnvironmentSensor, Htu, Lc709203f, Max17048, MeasurementResults,
+        },
         ledctl::BoardLed,
         net::wifi::{WiFi, RSSI_THRESHOLD},
         nvs::NonVolatileStorage,
         ota::{Ota, OtaHandle},
-        power::{get_reset_reason, mcu_sleep, ResetReasonExt},
-        usbctl, OsError, OsResult, ReportableError,
+        periph::AuxPeripherals,
+        phases::{self, Phase},
+        pm,
+        power::{get_reset_reason, ResetReasonExt},
+        safe_mode, stats, thermal, usbctl, OsError, OsResult, ReportableError,
     },
 };
 use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(any(feature = "ds18b20", feature = "pms5003", feature = "sds011"))]
+use esp_idf_svc::hal::gpio::AnyIOPin;
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+use esp_idf_svc::hal::{
+    uart::{config::Config as UartConfig, UartDriver},
+    units::Hertz,
+};
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
     hal::{
@@ -20,62 +68,328 @@ use esp_idf_svc::{
     },
     wifi::AccessPointInfo,
 };
+#[cfg(feature = "scd4x")]
+use pwmp_client::pwmp_msg::aliases::AirPressure;
 use pwmp_client::{
     ota::UpdateStatus,
     pwmp_msg::{settings::NodeSettings, version::Version, MsgId},
     PwmpClient,
 };
+use std::time::{Duration, Instant};
 
 static PWMP_MSG_ID: AtomicU8 = AtomicU8::new(0);
 
-#[allow(clippy::too_many_arguments, clippy::cognitive_complexity)]
+/// Addresses of optional I2C sensors, which are skipped while detecting the environment sensor.
+const AUX_I2C_ADDRS: &[u8] = &[
+    Max17048::DEV_ADDR,
+    Lc709203f::DEV_ADDR,
+    #[cfg(feature = "scd4x")]
+    Scd4x::DEV_ADDR,
+    #[cfg(feature = "as3935")]
+    As3935::DEV_ADDR,
+];
+
+/// Resolution used for DS18B20 probes.
+#[cfg(feature = "ds18b20")]
+const PROBE_RESOLUTION: Ds18b20Resolution = Ds18b20Resolution::Bits12;
+
+#[allow(
+    clippy::too_many_arguments,
+    clippy::too_many_lines,
+    clippy::cognitive_complexity,
+    clippy::needless_pass_by_value
+)]
 pub fn fw_main(
-    mut battery: Battery,
-    i2c: I2cDriver,
+    mut battery: Battery<impl BatteryChannel>,
+    fuel_gauge: Option<FuelGauge>,
+    #[cfg(feature = "solar")] mut charger: Option<Charger<impl BatteryChannel>>,
+    mut i2c: I2cDriver<'static>,
     modem: Modem<'static>,
     sys_loop: EspSystemEventLoop,
     temp_sensor: &InternalTempSensorDriver<'static>,
+    #[allow(unused_variables)] aux: AuxPeripherals,
     mut led: BoardLed,
     nvs: &NonVolatileStorage,
     ota: &mut Ota,
     cfg: &mut NodeSettings,
-) -> OsResult<()> {
+) -> OsResult<Duration> {
     if !ota.current_verified()? {
         log::warn!("Running unverified firmware");
     }
 
-    let (wifi, ap) = setup_wifi(modem, sys_loop)?;
-    log::debug!("Connecting to PWMP");
-    let mut pws = PwmpClient::new(PWMP_SERVER, &pwmp_msg_id_gen, None, None, None)?;
+    // Measured before anything draws extra current, to estimate the battery's internal resistance.
+    let bat_rest = match AnyBatterySource::new(fuel_gauge, &mut battery, &mut i2c)
+        .and_then(|mut source| source.read_all())
+    {
+        Ok(reading) => Some(reading),
+        // the ADC is measured again later
+        Err(why) if fuel_gauge.is_none() => {
+            log::warn!("Failed to measure battery at rest: {why}");
+            None
+        }
+        Err(why) => return Err(why),
+    };
+    let bat_rest_voltage = bat_rest.map(|reading| reading.voltage);
+
+    // the voltage at rest does not sag during transmissions, so it doesn't make the tier flap
+    let power_tier = if cfg.battery_ignore || usbctl::is_connected() {
+        PowerTier::Normal
+    } else {
+        bat_rest_voltage.map_or_else(policy::current, policy::update)
+    };
+    if power_tier != PowerTier::Normal {
+        log::warn!("Battery power tier: {power_tier}");
+    }
+
+    // optional sensors and updates are skipped in safe mode, in case they cause the crashes
+    let safe_mode = safe_mode::is_active();
+    let extras = power_tier.extras_enabled() && !safe_mode;
+
+    // The fan needs to warm up, so the sensor is started as early as possible.
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    let pm_sensor = if extras && schedule::every(PM_SENSOR_INTERVAL) {
+        start_pm_sensor(aux.particulate)
+            .inspect_err(|why| log::warn!("Failed to start particulate matter sensor: {why}"))
+            .ok()
+    } else {
+        log::debug!("Skipping particulate matter measurement in this cycle");
+        None
+    };
+
+    // The environment is measured before connecting, so that slow sensors can measure in the meantime.
+    let results = phases::measure(Phase::SensorRead, || {
+        setup_envsensor(&mut i2c).and_then(read_environment)
+    })?;
+    log::info!("{:.02}*C / {}%", results.temperature, results.humidity);
+
+    let cpu_die_temp = re_esp!(temp_sensor.get_celsius(), InternalTempSensorRead)?;
+    let thermal = thermal::update(results.temperature, cpu_die_temp);
+
+    #[cfg(feature = "solar")]
+    if let Some(charger) = &mut charger {
+        match charger.set_charging(thermal.charging) {
+            Ok(()) if !thermal.charging => thermal::charging_disabled(),
+            Ok(()) => (),
+            Err(why) => log::warn!("Failed to set charger state: {why}"),
+        }
+    }
+
+    // the radio draws the most current, and would heat the die up even more
+    if !thermal.operating {
+        log::warn!("Temperature outside operating range, skipping this cycle");
+        led.off();
+        return Ok(thermal.sleep_time(cfg.sleep_time() * power_tier.sleep_multiplier()));
+    }
+
+    #[cfg(feature = "scd4x")]
+    let co2_sensor = if extras {
+        start_co2_measurement(&mut i2c, results.air_pressure)
+            .inspect_err(|why| log::warn!("Failed to start CO2 measurement: {why}"))
+            .ok()
+    } else {
+        None
+    };
+
+    // the radio draws current spikes when it starts, which the battery may not be able to handle
+    let recovering = brownout::recovering();
+    if recovering {
+        log::warn!("Recovering from a brownout, letting the voltage settle");
+        pm::idle(|| std::thread::sleep(BROWNOUT_SETTLE_TIME));
+    }
 
-    log::debug!("Sending handshake request");
-    pws.perform_handshake(wifi.get_mac()?)?;
+    let (wifi, ap) = setup_wifi(
+        modem,
+        sys_loop,
+        safe_mode,
+        recovering.then_some(BROWNOUT_TX_POWER),
+    )?;
+    log::debug!("Connecting to PWMP");
+    let handshake = || -> OsResult<PwmpClient> {
+        let mut pws = PwmpClient::new(PWMP_SERVER, &pwmp_msg_id_gen, None, None, None)?;
+
+        log::debug!("Sending handshake request");
+        pws.perform_handshake(wifi.get_mac()?)?;
+
+        Ok(pws)
+    };
+    // the radio transmits during the handshake, so the battery is measured under load meanwhile
+    let (pws, bat_load_voltage) = phases::measure(Phase::Handshake, || {
+        if fuel_gauge.is_none() {
+            battery.read_min_during(handshake)
+        } else {
+            (handshake(), None)
+        }
+    });
+    let mut pws = pws?;
 
     log::debug!("Requesting app configuration");
-    read_appcfg(&mut pws, cfg)?;
+    phases::measure(Phase::Settings, || read_appcfg(&mut pws, cfg))?;
 
-    let bat_voltage = battery.read()?;
+    if WAKE_ALIGNMENT.is_some() {
+        log::debug!("Synchronizing time");
+        clock::sync().report("Failed to synchronize time");
+    }
+
+    // fuel gauges share the I2C bus with other sensors, so they are only read at rest
+    let bat_voltage = match bat_rest.filter(|_| fuel_gauge.is_some()) {
+        Some(reading) => reading.voltage,
+        None => phases::measure(Phase::SensorRead, || battery.read())?,
+    };
     if usbctl::is_connected() {
         log::warn!("Battery voltage measurement may be affected by USB power");
         cfg.battery_ignore = true;
     }
     log::info!("Battery: {bat_voltage:.02}V");
 
-    if (bat_voltage <= CRITICAL_VOLTAGE) && cfg.sbop && !cfg.battery_ignore {
+    // the voltage is meaningless while powered over USB, and sags while the radio is on
+    let bat_soc = bat_rest.filter(|_| !cfg.battery_ignore).map(|reading| {
+        reading
+            .soc
+            .unwrap_or_else(|| battery::soc::estimate(reading.voltage, results.temperature))
+    });
+    if let Some(soc) = bat_soc {
+        log::info!("Battery charge: {soc:.0}%");
+    }
+
+    // the sag under load depends on the signal strength, which would only add noise to the trend
+    let bat_days = bat_rest_voltage
+        .filter(|_| !cfg.battery_ignore)
+        .and_then(|voltage| battery::runtime::update(voltage, cfg.sleep_time()));
+    if let Some(days) = bat_days {
+        log::info!("Battery runtime left: {days:.01} days");
+    }
+
+    let bat_rate = bat_rest
+        .and_then(|reading| reading.charge_rate)
+        .filter(|_| !cfg.battery_ignore);
+
+    let bat_resistance = bat_rest_voltage
+        .zip(bat_load_voltage)
+        .filter(|_| !cfg.battery_ignore)
+        .and_then(|(rest, load)| battery::health::update(nvs, rest, load));
+    if let Some(resistance) = bat_resistance {
+        log::info!("Battery internal resistance: {resistance:.0}mOhm");
+    }
+
+    let bat_resistance_change =
+        bat_resistance.and_then(|resistance| battery::health::trend(nvs, resistance));
+    if let Some(change) = bat_resistance_change {
+        log::info!("Battery internal resistance change: {change:+.0}%");
+    }
+
+    #[cfg(feature = "solar")]
+    let charge_status = charger.and_then(|mut charger| {
+        charger
+            .read()
+            .inspect_err(|why| log::warn!("Failed to read solar charger: {why}"))
+            .ok()
+    });
+    #[cfg(feature = "solar")]
+    if let Some(status) = charge_status {
+        log::info!(
+            "Charger: {:?}, panel: {:.02}V",
+            status.state,
+            status.panel_voltage
+        );
+    }
+
+    #[allow(unused_mut)]
+    let mut sleep_time = cfg.sleep_time() * power_tier.sleep_multiplier();
+
+    #[cfg(feature = "solar")]
+    if let Some(status) = charge_status {
+        sleep_time = charger::adjust_sleep_time(sleep_time, status);
+    }
+
+    if power_tier == PowerTier::Critical && cfg.sbop {
         log::warn!("Battery voltage too low, activating sBOP");
+        report_power_tier(&mut pws);
 
-        pws.send_notification("Battery voltage too low, activating sBOP")
-            .report("Failed to send sBOP notification");
+        // the fan would keep running otherwise
+        #[cfg(any(feature = "pms5003", feature = "sds011"))]
+        drop(pm_sensor);
 
-        mcu_sleep(None);
+        return Ok(battery::protection::enter());
     }
 
-    let env_sensor = setup_envsensor(i2c)?;
-    let cpu_die_temp = re_esp!(temp_sensor.get_celsius(), InternalTempSensorRead)?;
+    let mut channels = Channels::default();
+
+    if let Some(soc) = bat_soc {
+        channels.push("battery_soc", soc, "%");
+    }
+
+    if let Some(rate) = bat_rate {
+        channels.push("battery_rate", rate, "%/h");
+    }
+
+    if let Some(days) = bat_days {
+        channels.push("battery_days", days, "d");
+    }
+
+    if let Some(resistance) = bat_resistance {
+        channels.push("battery_resistance", resistance, "mOhm");
+    }
+
+    if let Some(change) = bat_resistance_change {
+        channels.push("battery_resistance_change", change, "%");
+    }
+
+    #[cfg(feature = "solar")]
+    if let Some(status) = charge_status {
+        channels.push("solar_voltage", status.panel_voltage, "V");
+        channels.push(
+            "charging",
+            f32::from(u8::from(status.state == ChargeState::Charging)),
+            "",
+        );
+    }
+
+    #[cfg(any(
+        feature = "ds18b20",
+        feature = "scd4x",
+        feature = "pms5003",
+        feature = "sds011"
+    ))]
+    let aux_read = phases::begin(Phase::SensorRead);
+
+    #[cfg(feature = "ds18b20")]
+    if extras {
+        read_probes(aux.onewire, &mut channels).report("Failed to read DS18B20 probes");
+    }
+
+    #[cfg(feature = "scd4x")]
+    if let Some(mut sensor) = co2_sensor {
+        match sensor.read_measurement() {
+            Ok(co2) => {
+                log::info!("CO2: {co2}ppm");
+                channels.push("co2", f32::from(co2), "ppm");
+            }
+            Err(why) => log::warn!("Failed to read CO2 measurement: {why}"),
+        }
+    }
+
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    if let Some((sensor, warmup)) = pm_sensor {
+        read_particulate(sensor, &warmup, &mut channels)
+            .report("Failed to read particulate matter sensor");
+    }
+
+    #[cfg(any(
+        feature = "ds18b20",
+        feature = "scd4x",
+        feature = "pms5003",
+        feature = "sds011"
+    ))]
+    drop(aux_read);
+
+    #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+    pulse::collect(&mut channels);
+
+    #[cfg(feature = "as3935")]
+    lightning::collect(&mut channels);
 
-    let results = read_environment(env_sensor)?;
-    log::info!("{:.02}*C / {}%", results.temperature, results.humidity);
     log::debug!("Posting results");
+    let post = phases::begin(Phase::Post);
     pws.post_measurements(
         results.temperature,
         results.humidity,
@@ -85,6 +399,82 @@ pub fn fw_main(
         &ap.ssid,
         ap.signal_strength,
     )?;
+    report_power_tier(&mut pws);
+
+    if let Some(offline) = battery::protection::pending_report() {
+        log::info!("Reporting recovery from protective sleep");
+
+        match pws.send_notification(format!(
+            "Battery has recovered, node was offline for {:.01} hours",
+            offline.as_secs_f32() / 3600.0
+        )) {
+            Ok(()) => battery::protection::report_sent(),
+            Err(why) => log::warn!("Failed to report recovery from protective sleep: {why}"),
+        }
+    }
+
+    // only the main measurements are sent as a heartbeat, the rest waits until the battery recovers
+    if power_tier.is_minimal() {
+        log::debug!("Sending heartbeat only");
+        led.off();
+        return Ok(sleep_time);
+    }
+
+    let channels_sent = channels.send(&mut pws);
+
+    // the counts are kept for the next report, if they couldn't be sent
+    #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+    if channels_sent.is_ok() {
+        pulse::reset();
+    }
+
+    #[cfg(feature = "as3935")]
+    if channels_sent.is_ok() {
+        lightning::reset();
+    }
+
+    channels_sent.report("Failed to send additional channels");
+
+    #[cfg(feature = "as3935")]
+    if let Some(strike) = lightning::pending_notification() {
+        let distance = strike
+            .distance
+            .map_or_else(|| "out of range".to_string(), |km| format!("{km}km away"));
+
+        log::info!("Sending lightning notification");
+        match pws.send_notification(format!(
+            "Lightning detected, storm front {distance} (energy {})",
+            strike.energy
+        )) {
+            Ok(()) => lightning::notification_sent(),
+            Err(why) => log::warn!("Failed to send lightning notification: {why}"),
+        }
+    }
+
+    if battery::runtime::warning_due(bat_days) {
+        log::warn!("Battery is running low");
+
+        match pws.send_notification(format!(
+            "Battery will run out in about {:.0} days",
+            bat_days.unwrap_or_default()
+        )) {
+            Ok(()) => battery::runtime::warning_sent(),
+            Err(why) => log::warn!("Failed to send battery runtime warning: {why}"),
+        }
+    }
+
+    if battery::health::warning_due(bat_resistance) {
+        log::warn!("Battery internal resistance is high");
+
+        match pws.send_notification(format!(
+            "Battery internal resistance is high ({:.0}mOhm at {:.01}*C), it may need to be replaced",
+            bat_resistance.unwrap_or_default(),
+            results.temperature
+        )) {
+            Ok(()) => battery::health::warning_sent(),
+            Err(why) => log::warn!("Failed to send battery health warning: {why}"),
+        }
+    }
 
     let reset_reason = get_reset_reason();
     if reset_reason.is_abnormal() {
@@ -96,6 +486,35 @@ pub fn fw_main(
         log::debug!("Reset reason ({reset_reason:?}) is normal");
     }
 
+    if let Some(notification) = thermal::pending_notification() {
+        log::info!("Reporting temperature excursion");
+
+        match pws.send_notification(notification) {
+            Ok(()) => thermal::notification_sent(),
+            Err(why) => log::warn!("Failed to report temperature excursion: {why}"),
+        }
+    }
+
+    if let Some(count) = brownout::pending_report() {
+        log::info!("Reporting brownout resets");
+
+        match pws.send_notification(format!(
+            "Detected {count} brownout resets since the last report"
+        )) {
+            Ok(()) => brownout::report_sent(nvs),
+            Err(why) => log::warn!("Failed to report brownout resets: {why}"),
+        }
+    }
+
+    if let Some(notification) = safe_mode::pending_notification() {
+        log::info!("Reporting safe mode change");
+
+        match pws.send_notification(notification) {
+            Ok(()) => safe_mode::notification_sent(nvs),
+            Err(why) => log::warn!("Failed to report safe mode change: {why}"),
+        }
+    }
+
     if let Some(error) = nvs.get_last_os_error()? {
         log::info!("Reporting error from previous run ({error})");
 
@@ -109,6 +528,9 @@ pub fn fw_main(
         log::debug!("No error detected from previous run");
     }
 
+    phases::report_if_due(&mut pws);
+    stats::report_if_due(&mut pws);
+
     if ota.report_needed()? {
         let success = !ota.rollback_detected()?;
 
@@ -133,11 +555,24 @@ pub fn fw_main(
         log::debug!("No update report needed");
     }
 
-    log::debug!("Checking for updates");
-    if check_ota(&mut pws)? {
+    drop(post);
+
+    if !extras {
+        log::debug!(
+            "Skipping update check {}",
+            if safe_mode {
+                "in safe mode"
+            } else {
+                "to save power"
+            }
+        );
+    } else if recovering {
+        log::debug!("Skipping update check while recovering from a brownout");
+    } else if phases::measure(Phase::OtaCheck, || check_ota(&mut pws))? {
+        stats::record_ota_attempt();
         let mut handle = ota.begin_update()?;
 
-        if let Err(why) = begin_update(&mut pws, &mut handle) {
+        if let Err(why) = phases::measure(Phase::Update, || begin_update(&mut pws, &mut handle)) {
             log::error!("OTA failed: {why}, aborting");
             handle.cancel()?;
         }
@@ -146,20 +581,43 @@ pub fn fw_main(
     } // Handle will be dropped and the update should finalize
 
     led.off();
-    Ok(())
+    Ok(sleep_time)
+}
+
+/// Notify the server about a change of the battery power tier, if it was not reported yet.
+fn report_power_tier(pws: &mut PwmpClient) {
+    let Some(tier) = policy::pending_report() else {
+        return;
+    };
+
+    log::info!("Reporting battery power tier change");
+    match pws.send_notification(format!("Battery power tier changed to {tier}")) {
+        Ok(()) => policy::report_sent(tier),
+        Err(why) => log::warn!("Failed to report battery power tier: {why}"),
+    }
 }
 
 fn setup_wifi(
     modem: Modem<'static>,
     sys_loop: EspSystemEventLoop,
+    minimal: bool,
+    max_tx_power: Option<i8>,
 ) -> OsResult<(WiFi, AccessPointInfo)> {
     log::debug!("Starting WiFi setup");
-    let mut wifi = WiFi::new(modem, sys_loop)?;
+    let (mut wifi, mut networks) = phases::measure(Phase::WifiScan, || -> OsResult<_> {
+        let mut wifi = WiFi::new(modem, sys_loop)?;
 
-    log::debug!("Starting WiFi scan");
-    #[cfg(debug_assertions)]
-    let scan_start = std::time::Instant::now();
-    let mut networks = wifi.scan()?;
+        if let Some(dbm) = max_tx_power {
+            log::debug!("Limiting transmit power to {dbm}dBm");
+            wifi.set_max_tx_power(dbm)
+                .report("Failed to limit transmit power");
+        }
+
+        log::debug!("Starting WiFi scan");
+        let networks = wifi.scan()?;
+
+        Ok((wifi, networks))
+    })?;
 
     #[cfg(debug_assertions)]
     {
@@ -169,10 +627,8 @@ fn setup_wifi(
             .collect::<Vec<String>>()
             .join(", ");
 
-        log::debug!(
-            "Found networks: {formatted_network_names} in {:.02?}",
-            scan_start.elapsed()
-        );
+        // the duration is logged with the phases
+        log::debug!("Found networks: {formatted_network_names}");
     }
 
     // filter out APs with RSSI >= RSSI_THRESHOLD
@@ -180,6 +636,12 @@ fn setup_wifi(
     // sort by signal strength
     networks.sort_by_key(|b| std::cmp::Reverse(b.signal_strength));
 
+    if minimal {
+        log::debug!("Only trying the strongest known network");
+        networks.retain(|ap| WIFI_NETWORKS.iter().any(|candidate| candidate.0 == ap.ssid));
+        networks.truncate(1);
+    }
+
     if networks.is_empty() {
         log::warn!("No usable networks found");
         return Err(OsError::NoInternet);
@@ -196,14 +658,17 @@ fn setup_wifi(
 
         log::debug!("Connecting to {} ({}dBm)", ap.ssid, ap.signal_strength);
 
-        let start = std::time::Instant::now();
+        let start = Instant::now();
         match wifi.connect(&ap, psk, WIFI_TIMEOUT) {
             Ok(()) => {
                 log::debug!("Connected in {:.02?}", start.elapsed());
                 log::debug!("IP: {}", wifi.get_ip_info()?.ip);
                 return Ok((wifi, ap));
             }
-            Err(why) => log::error!("Failed to connect: {why}"),
+            Err(why) => {
+                log::error!("Failed to connect: {why}");
+                stats::record_wifi_failure();
+            }
         }
     }
 
@@ -224,10 +689,10 @@ fn read_appcfg(pws: &mut PwmpClient, appcfg: &mut NodeSettings) -> OsResult<()>
     Ok(())
 }
 
-fn setup_envsensor(mut i2c_driver: I2cDriver<'_>) -> OsResult<AnySensor<'_>> {
+fn setup_envsensor<'s>(i2c_driver: &'s mut I2cDriver<'static>) -> OsResult<AnySensor<'s>> {
     let mut working = None;
 
-    for addr in 1..128 {
+    for addr in (1..128).filter(|addr| !AUX_I2C_ADDRS.contains(addr)) {
         if i2c_driver.write(addr, &[], 1000).is_ok() {
             log::debug!("Found device @ I2C/0x{addr:X}");
             working = Some(addr);
@@ -267,7 +732,76 @@ fn read_environment(mut env: AnySensor) -> OsResult<MeasurementResults> {
     })
 }
 
+#[cfg(feature = "scd4x")]
+fn start_co2_measurement<'s>(
+    i2c_driver: &'s mut I2cDriver<'static>,
+    air_pressure: Option<AirPressure>,
+) -> OsResult<Scd4x<'s>> {
+    let mut sensor = Scd4x::new_with_driver(i2c_driver)?;
+    sensor.set_self_calibration(CO2_SELF_CALIBRATION)?;
+
+    match air_pressure {
+        Some(pressure) => sensor.set_ambient_pressure(pressure)?,
+        None => sensor.set_altitude(ALTITUDE)?,
+    }
+
+    sensor.start_measurement()?;
+    Ok(sensor)
+}
+
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+fn start_pm_sensor(uart: UartPeripherals) -> OsResult<(PmSensor, Warmup)> {
+    let driver = re_esp!(
+        UartDriver::new(
+            uart.uart,
+            uart.tx,
+            uart.rx,
+            Option::<AnyIOPin>::None,
+            Option::<AnyIOPin>::None,
+            &UartConfig::new().baudrate(Hertz(9600)),
+        ),
+        UartInit
+    )?;
+
+    let sensor = PmSensor::new_with_driver(driver)?;
+    Ok((sensor, Warmup::start(PmSensor::WARMUP_TIME)))
+}
+
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+fn read_particulate(
+    mut sensor: PmSensor,
+    warmup: &Warmup,
+    channels: &mut Channels,
+) -> OsResult<()> {
+    warmup.wait();
+    let results = sensor.read()?;
+
+    if let Some(pm1_0) = results.pm1_0 {
+        channels.push("pm1_0", pm1_0, "ug/m3");
+    }
+    channels.push("pm2_5", results.pm2_5, "ug/m3");
+    channels.push("pm10", results.pm10, "ug/m3");
+
+    Ok(())
+}
+
+#[cfg(feature = "ds18b20")]
+fn read_probes(pin: AnyIOPin<'static>, channels: &mut Channels) -> OsResult<()> {
+    let bus = PinOneWire::new(pin)?;
+    let mut probes = Ds18b20::new(bus, PROBE_RESOLUTION)?;
+
+    for (rom, result) in probes.measure()? {
+        match result {
+            Ok(temperature) => channels.push(format!("ds18b20_{rom}"), temperature, "C"),
+            Err(why) => log::warn!("Failed to read probe {rom}: {why}"),
+        }
+    }
+
+    Ok(())
+}
+
 fn check_ota(pws: &mut PwmpClient) -> OsResult<bool> {
+    log::debug!("Checking for updates");
     let current_version =
         Version::parse(env!("CARGO_PKG_VERSION")).ok_or(OsError::IllegalFirmwareVersion)?;
 
@@ -7,10 +7,23 @@ use esp_idf_svc::hal::{
     temp_sensor::{config::Config as TempSensorConfig, TempSensorDriver},
     units::FromValueType,
 };
-use std::time::Instant;
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
 use sysc::{
-    battery::Battery, ledctl::BoardLed, ota::Ota, periph::SystemPeripherals, power::mcu_sleep,
-    usbctl, OsError,
+    battery::{policy, source::AnyBatterySource, Battery},
+    button::Press,
+    ledctl::BoardLed,
+    nvs::NonVolatileStorage,
+    ota::Ota,
+    periph::SystemPeripherals,
+    phases::{self, Phase},
+    power::mcu_sleep,
+    supervisor::Supervisor,
+    usbctl,
+    wake::WakeSources,
+    OsError,
 };
 
 mod config;
@@ -19,7 +32,10 @@ mod sysc;
 
 #[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
 fn main() {
+    let boot = Instant::now();
     esp_idf_svc::sys::link_patches();
+    sysc::phases::record_boot();
+    sysc::power::release_sleep_holds();
 
     // Turn off logging when USB is not connected
     if !usbctl::is_connected() {
@@ -37,18 +53,64 @@ fn main() {
     );
     log::info!("(C) Fábián Varga 2025");
 
+    sysc::pm::configure().report("Failed to configure power management");
+
     #[cfg(debug_assertions)]
-    {
-        log::debug!("Using ESP-IDF {}", sysc::get_idf_version());
+    log::debug!("Using ESP-IDF {}", sysc::get_idf_version());
+
+    if cfg!(debug_assertions) {
         log::debug!("Disabling brownout detector");
         sysc::brownout::disable_brownout_detector();
+    } else if let Some(level) = config::BROWNOUT_LEVEL {
+        sysc::brownout::set_threshold(level).report("Failed to set brownout threshold");
     }
 
     log::debug!("Initializing system peripherals");
     let peripherals = SystemPeripherals::take();
 
+    log::debug!("Initializing wake-up sources");
+    let wake_sources = WakeSources {
+        #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+        pulse_counter: sysc::pulse::PulseCounter::new(peripherals.pulse)
+            .expect("Failed to initialize pulse counter"),
+        #[cfg(feature = "as3935")]
+        lightning: sysc::lightning::LightningIrq::new(peripherals.lightning_irq),
+    };
+
+    #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+    match sysc::wake_stub::take_wakes() {
+        0 => (),
+        wakes => log::debug!("{wakes} pulse wake-ups were handled by the wake stub"),
+    }
+
+    let button = sysc::button::handle_wakeup()
+        .inspect_err(|why| log::warn!("Failed to read wake-up button: {why}"))
+        .ok()
+        .flatten();
+
+    #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+    if wake_sources.pulse_counter.handle_wakeup() && button.is_none() {
+        wake_sources.resume_sleep();
+    }
+
+    log::debug!("Initializing I2C bus");
+    let mut i2c = I2cDriver::new(
+        peripherals.i2c.i2c,
+        peripherals.i2c.sda,
+        peripherals.i2c.scl,
+        &Config::default().baudrate(400u32.kHz().into()),
+    )
+    .expect("Failed to initialize I2C");
+
+    #[cfg(feature = "as3935")]
+    match sysc::lightning::handle_wakeup(&mut i2c) {
+        Ok(true) => wake_sources.resume_sleep(),
+        Ok(false) => (),
+        Err(why) => log::warn!("Failed to handle lightning sensor: {why}"),
+    }
+
     log::debug!("Initializing system LED");
-    let led = BoardLed::new(
+    let mut led = BoardLed::new(
         peripherals.onboard_led.pin.degrade_output(),
         peripherals.onboard_led.invert,
     )
@@ -75,20 +137,62 @@ fn main() {
     );
 
     log::debug!("Initializing NVS");
-    let nvs = sysc::nvs::NonVolatileStorage::new().expect("Failed to initialize NVS");
+    let nvs = NonVolatileStorage::new().expect("Failed to initialize NVS");
 
-    log::debug!("Initializing system Battery");
-    let battery = Battery::new(peripherals.battery.adc, peripherals.battery.pin)
-        .expect("Failed to initialize battery ADC");
+    let reset_reason = sysc::power::get_reset_reason();
+    sysc::stats::boot(&nvs, reset_reason);
 
-    log::debug!("Initializing I2C bus");
-    let i2c = I2cDriver::new(
-        peripherals.i2c.i2c,
-        peripherals.i2c.sda,
-        peripherals.i2c.scl,
-        &Config::default().baudrate(400u32.kHz().into()),
+    let overrun = sysc::supervisor::take_overrun();
+    if let Some(overrun) = &overrun {
+        log::warn!("Previous run was cut short: {}", overrun.error);
+        sysc::stats::record_failure(&overrun.error);
+        sysc::stats::finish(&nvs, overrun.awake);
+        nvs.store_last_os_error(&overrun.error)
+            .report("Failed to store error in NVS");
+    }
+
+    sysc::safe_mode::check(&nvs, reset_reason, overrun.is_some());
+    sysc::brownout::check(&nvs, reset_reason);
+
+    log::debug!("Initializing system Battery");
+    let mut battery = Battery::new(
+        peripherals.battery.adc,
+        peripherals.battery.pin,
+        peripherals.battery.config,
     )
-    .expect("Failed to initialize I2C");
+    .expect("Failed to initialize battery ADC");
+
+    match nvs.get_battery_calibration() {
+        Ok(calibration) => battery.set_calibration(calibration),
+        Err(why) => log::warn!("Failed to load battery calibration: {why}"),
+    }
+
+    let fuel_gauge = peripherals.battery.fuel_gauge;
+    let protective_sleep = AnyBatterySource::new(fuel_gauge, &mut battery, &mut i2c)
+        .inspect_err(|why| log::warn!("Failed to set up battery source: {why}"))
+        .ok()
+        .and_then(|mut source| sysc::battery::protection::check(&mut source));
+
+    if let Some(sleep_time) = protective_sleep {
+        sysc::safe_mode::finish(&nvs, true);
+        finish_cycle(&nvs, &wake_sources, boot, sleep_time);
+    }
+
+    #[cfg(feature = "solar")]
+    let charger = sysc::charger::Charger::new(battery.adc(), peripherals.solar)
+        .inspect_err(|why| log::warn!("Failed to initialize solar charger: {why}"))
+        .ok();
+
+    phases::record(Phase::PeripheralInit, boot.elapsed());
+
+    if button == Some(Press::Long) {
+        led.on();
+        sysc::console::run_maintenance(&mut battery, &nvs).report("Failed to open console");
+    } else if usbctl::is_connected() {
+        sysc::console::run_if_requested(&mut battery, &nvs).report("Failed to open console");
+    }
+
+    let init_start = Instant::now();
 
     log::debug!("Initializing internal temperature sensor");
     let mut temp_sensor =
@@ -101,26 +205,55 @@ fn main() {
     log::debug!("Initializing app configuration");
     let mut appcfg = config::get_settings();
 
+    // not inside the log macro, which is compiled out above the maximum level
+    let cycle = sysc::schedule::advance();
+    log::debug!("Starting wake cycle {cycle}");
+    log::debug!("Wake-up cause: {:?}", sysc::power::get_wakeup_cause());
+
+    phases::record(Phase::PeripheralInit, init_start.elapsed());
+
     log::info!("Staring main");
 
+    // the supervisor's timer must not access the state of the main task, so its sleep time is computed up front
+    let overrun_sleep = sysc::clock::sleep_time(
+        sysc::safe_mode::sleep_time(appcfg.sleep_time() * policy::current().sleep_multiplier()),
+        boot.elapsed(),
+    );
+    let wake_sources = Arc::new(Mutex::new(wake_sources));
+    let supervisor = Supervisor::start(boot, overrun_sleep, Arc::clone(&wake_sources))
+        .inspect_err(|why| log::warn!("Failed to start supervisor: {why}"))
+        .ok();
+
     let start = Instant::now();
     let fw_exit = firmware::fw_main(
         battery,
+        fuel_gauge,
+        #[cfg(feature = "solar")]
+        charger,
         i2c,
         peripherals.wifi.modem,
         peripherals.wifi.sys_loop,
         &temp_sensor,
+        peripherals.aux,
         led,
         &nvs,
         &mut ota,
         &mut appcfg,
     );
     let runtime = start.elapsed();
+    drop(supervisor);
 
-    match fw_exit {
-        Ok(()) => log::info!("Tasks completed successfully"),
+    sysc::safe_mode::finish(&nvs, fw_exit.is_ok());
+
+    let sleep_time = match fw_exit {
+        Ok(sleep_time) => {
+            log::info!("Tasks completed successfully");
+            sysc::stats::record_success();
+            sleep_time
+        }
         Err(why) => {
             log::error!("OS Error: {why}");
+            sysc::stats::record_failure(&why);
 
             nvs.store_last_os_error(&why)
                 .report("Failed to store error in NVS");
@@ -132,10 +265,29 @@ fn main() {
 
             ota.inc_failiures()
                 .expect("Failed to increment failiure count");
+
+            appcfg.sleep_time()
         }
-    }
+    };
     log::info!("Tasks completed in {runtime:.02?}");
 
-    log::debug!("Sleeping for {:?}", appcfg.sleep_time());
-    mcu_sleep(Some(appcfg.sleep_time()));
+    let wake_sources = wake_sources.lock().unwrap_or_else(PoisonError::into_inner);
+    finish_cycle(&nvs, &wake_sources, boot, sleep_time);
+}
+
+/// Record the end of the wake cycle, that has started at `boot`, and put the node to sleep for `sleep_time`.
+fn finish_cycle(
+    nvs: &NonVolatileStorage,
+    wake_sources: &WakeSources,
+    boot: Instant,
+    sleep_time: Duration,
+) -> ! {
+    phases::finish();
+
+    let sleep_time = sysc::safe_mode::sleep_time(sleep_time);
+
+    sysc::stats::finish(nvs, boot.elapsed());
+    let sleep_time = sysc::clock::sleep_time(sleep_time, boot.elapsed());
+
+    wake_sources.sleep(sleep_time);
 }
@@ -0,0 +1,105 @@
+//! Two-point calibration of the battery voltage measurement.
+//!
+//! Resistor tolerances and the non-linearity of the ADC make the measured voltage differ from the real one,
+//! usually by a small gain and offset error. These are corrected using two reference points, each being
+//! a measured voltage and the real voltage measured with a multimeter at the same time:
+//!
+//! ```text
+//! real = gain * measured + offset
+//! ```
+//!
+//! The calibration is unique to every node, so it's stored in NVS. It's set up using the `cal` commands
+//! of the USB [console](crate::sysc::console).
+
+/// Minimum difference of the two reference voltages.
+///
+/// Points that are too close together would amplify the measurement noise.
+const MIN_POINT_DISTANCE: f32 = 0.2;
+/// Range of accepted gain values, anything outside of it is most likely a mistake.
+const GAIN_RANGE: (f32, f32) = (0.8, 1.2);
+
+/// Gain and offset correction of the battery voltage.
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    /// Gain correction
+    pub gain: f32,
+
+    /// Offset correction in volts
+    pub offset: f32,
+}
+
+/// A single reference point.
+#[derive(Clone, Copy, Debug)]
+pub struct CalibrationPoint {
+    /// Voltage reported by the uncalibrated measurement
+    pub measured: f32,
+
+    /// Real voltage of the battery
+    pub actual: f32,
+}
+
+impl Calibration {
+    /// Calculate the calibration from two reference points.
+    ///
+    /// Returns `None` if the points are too close together, or if the resulting gain is implausible.
+    pub fn from_points(a: CalibrationPoint, b: CalibrationPoint) -> Option<Self> {
+        let measured_delta = b.measured - a.measured;
+
+        if measured_delta.abs() < MIN_POINT_DISTANCE {
+            return None;
+        }
+
+        let gain = (b.actual - a.actual) / measured_delta;
+        if !(GAIN_RANGE.0..=GAIN_RANGE.1).contains(&gain) {
+            return None;
+        }
+
+        Some(Self {
+            gain,
+            offset: gain.mul_add(-a.measured, a.actual),
+        })
+    }
+
+    /// Apply the correction to a measured voltage.
+    pub const fn apply(self, voltage: f32) -> f32 {
+        self.gain.mul_add(voltage, self.offset)
+    }
+
+    /// Pack the calibration into a single integer for storage.
+    pub const fn to_bits(self) -> u64 {
+        pack(self.gain, self.offset)
+    }
+
+    /// Unpack a calibration packed using [`to_bits()`](Self::to_bits).
+    pub const fn from_bits(bits: u64) -> Self {
+        let (gain, offset) = unpack(bits);
+        Self { gain, offset }
+    }
+}
+
+impl CalibrationPoint {
+    /// Pack the point into a single integer for storage.
+    pub const fn to_bits(self) -> u64 {
+        pack(self.measured, self.actual)
+    }
+
+    /// Unpack a point packed using [`to_bits()`](Self::to_bits).
+    pub const fn from_bits(bits: u64) -> Self {
+        let (measured, actual) = unpack(bits);
+        Self { measured, actual }
+    }
+}
+
+/// Pack two floats into an integer.
+const fn pack(high: f32, low: f32) -> u64 {
+    ((high.to_bits() as u64) << 32) | low.to_bits() as u64
+}
+
+/// Unpack two floats packed using [`pack()`].
+#[allow(clippy::cast_possible_truncation)]
+const fn unpack(bits: u64) -> (f32, f32) {
+    (
+        f32::from_bits((bits >> 32) as u32),
+        f32::from_bits(bits as u32),
+    )
+}
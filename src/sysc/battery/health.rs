@@ -0,0 +1,227 @@
+//! Battery health tracking using the internal resistance.
+//!
+//! ## How it works
+//! - The voltage is measured at rest before the radio is started, and again while it transmits the handshake.
+//!   The lowest voltage measured during the handshake is used, since the radio only draws its peak current in bursts.
+//! - The sag between the two, divided by the extra current drawn by the radio ([`BATTERY_LOAD_CURRENT`]),
+//!   is the internal resistance of the battery.
+//! - Single estimates are noisy, so they are smoothed using an exponential moving average kept in RTC memory.
+//!   The average is also checkpointed to NVS when it changes significantly, so it survives a power loss
+//!   without wearing out the flash.
+//! - Once a day, the average is folded into a long-term baseline in NVS, which follows it over several weeks.
+//!   The change of the resistance is reported against this baseline.
+//!
+//! The internal resistance rises as the battery ages, and also in the cold. A high resistance means
+//! that the voltage sags more during transmissions, which can eventually cause brownouts.
+
+use crate::{
+    config::{BATTERY_LOAD_CURRENT, BATTERY_RESISTANCE_WARNING},
+    sysc::{nvs::NonVolatileStorage, schedule::now_us, ReportableError},
+};
+use std::{
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    time::Duration,
+};
+
+/// Weight of a new estimate in the moving average.
+const SMOOTHING: f32 = 0.1;
+/// Relative change of the average, after which it's written to NVS.
+const CHECKPOINT_CHANGE: f32 = 0.05;
+/// Estimates above this are considered measurement errors (mOhm).
+const MAX_RESISTANCE: f32 = 2000.0;
+/// Marks an empty value.
+const NO_VALUE: u32 = u32::MAX;
+/// Interval of updating the long-term baseline.
+const BASELINE_INTERVAL: Duration = Duration::from_hours(24);
+/// Weight of a daily value in the long-term baseline, which makes it follow the resistance over about 4 weeks.
+const BASELINE_SMOOTHING: f32 = 1.0 / 28.0;
+
+/// Bits of the smoothed internal resistance in mOhm, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static RESISTANCE: AtomicU32 = AtomicU32::new(NO_VALUE);
+
+/// Bits of the internal resistance last written to NVS, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static CHECKPOINT: AtomicU32 = AtomicU32::new(NO_VALUE);
+
+/// Whether the high resistance warning was already sent, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Long-term baseline of the internal resistance, kept in NVS.
+#[derive(Clone, Copy)]
+pub struct Baseline {
+    /// Internal resistance in mOhm.
+    pub resistance: f32,
+
+    /// System time of the last update in seconds.
+    pub updated_s: u32,
+}
+
+/// Estimate the internal resistance from the voltages at rest and under load, and update the average.
+///
+/// Returns the smoothed internal resistance in mOhm, or `None` if none is known yet.
+pub fn update(nvs: &NonVolatileStorage, rest_voltage: f32, load_voltage: f32) -> Option<f32> {
+    let previous = load(&RESISTANCE).or_else(|| {
+        // RTC memory was lost, continue from the last checkpoint
+        let checkpoint = nvs
+            .get_battery_resistance()
+            .inspect_err(|why| log::warn!("Failed to read battery resistance checkpoint: {why}"))
+            .ok()
+            .flatten();
+
+        store(&CHECKPOINT, checkpoint);
+        checkpoint
+    });
+
+    let Some(estimate) = estimate(rest_voltage, load_voltage, BATTERY_LOAD_CURRENT) else {
+        log::debug!("Voltage sag is implausible, ignoring it");
+        return previous;
+    };
+    log::debug!("Battery resistance estimate: {estimate:.0}mOhm");
+
+    let resistance = previous.map_or(estimate, |previous| {
+        (estimate - previous).mul_add(SMOOTHING, previous)
+    });
+    store(&RESISTANCE, Some(resistance));
+
+    if needs_checkpoint(load(&CHECKPOINT), resistance) {
+        log::debug!("Storing battery resistance checkpoint");
+        nvs.store_battery_resistance(resistance)
+            .report("Failed to store battery resistance");
+        store(&CHECKPOINT, Some(resistance));
+    }
+
+    Some(resistance)
+}
+
+/// Update the long-term baseline with the smoothed `resistance`.
+///
+/// Returns the change of `resistance` against the baseline in percent, or `None` if there is no baseline.
+#[allow(clippy::cast_possible_truncation)]
+pub fn trend(nvs: &NonVolatileStorage, resistance: f32) -> Option<f32> {
+    let baseline = nvs
+        .get_battery_resistance_baseline()
+        .inspect_err(|why| log::warn!("Failed to read battery resistance baseline: {why}"))
+        .ok()
+        .flatten();
+
+    let updated = update_baseline(baseline, resistance, (now_us() / 1_000_000) as u32);
+    if let Some(updated) = updated {
+        log::debug!("Storing battery resistance baseline");
+        nvs.store_battery_resistance_baseline(updated)
+            .report("Failed to store battery resistance baseline");
+    }
+
+    change(updated.or(baseline)?.resistance, resistance)
+}
+
+/// Returns whether the high resistance warning should be sent.
+///
+/// The warning is sent again only after the resistance has dropped below the threshold.
+pub fn warning_due(resistance: Option<f32>) -> bool {
+    let Some(resistance) = resistance else {
+        return false;
+    };
+
+    if resistance < BATTERY_RESISTANCE_WARNING {
+        WARNED.store(false, Ordering::Relaxed);
+        return false;
+    }
+
+    !WARNED.load(Ordering::Relaxed)
+}
+
+/// Mark the high resistance warning as sent.
+pub fn warning_sent() {
+    WARNED.store(true, Ordering::Relaxed);
+}
+
+/// Estimate the internal resistance in mOhm from the voltages at rest and under load.
+///
+/// `load_current` is the extra current drawn under load in amperes.
+/// A negative sag is measurement noise, it's counted as no sag, so that the noise averages out.
+/// Returns `None` if the result is implausible.
+pub fn estimate(rest_voltage: f32, load_voltage: f32, load_current: f32) -> Option<f32> {
+    let sag = (rest_voltage - load_voltage).max(0.0);
+
+    if load_current <= 0.0 {
+        return None;
+    }
+
+    let resistance = sag / load_current * 1000.0;
+    (resistance <= MAX_RESISTANCE).then_some(resistance)
+}
+
+/// Returns whether `current` differs enough from the last `checkpoint` to be written to NVS.
+pub fn needs_checkpoint(checkpoint: Option<f32>, current: f32) -> bool {
+    checkpoint.is_none_or(|checkpoint| {
+        (current - checkpoint).abs() > checkpoint.abs() * CHECKPOINT_CHANGE
+    })
+}
+
+/// Fold `resistance` into the long-term `baseline`, if it's due.
+///
+/// Returns the new baseline, or `None` if it has not changed.
+pub fn update_baseline(
+    baseline: Option<Baseline>,
+    resistance: f32,
+    now_s: u32,
+) -> Option<Baseline> {
+    let Some(baseline) = baseline else {
+        return Some(Baseline {
+            resistance,
+            updated_s: now_s,
+        });
+    };
+
+    // the system time has jumped back (e.g. after a power loss), so restart the interval
+    if now_s < baseline.updated_s {
+        return Some(Baseline {
+            updated_s: now_s,
+            ..baseline
+        });
+    }
+
+    if u64::from(now_s - baseline.updated_s) < BASELINE_INTERVAL.as_secs() {
+        return None;
+    }
+
+    Some(Baseline {
+        resistance: (resistance - baseline.resistance)
+            .mul_add(BASELINE_SMOOTHING, baseline.resistance),
+        updated_s: now_s,
+    })
+}
+
+/// Returns the change of `current` against `baseline` in percent.
+pub fn change(baseline: f32, current: f32) -> Option<f32> {
+    (baseline > 0.0).then(|| (current - baseline) / baseline * 100.0)
+}
+
+impl Baseline {
+    /// Pack the baseline into a single integer for storage.
+    pub const fn to_bits(self) -> u64 {
+        ((self.resistance.to_bits() as u64) << 32) | self.updated_s as u64
+    }
+
+    /// Unpack a baseline packed by [`to_bits()`](Self::to_bits).
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_bits(bits: u64) -> Self {
+        Self {
+            resistance: f32::from_bits((bits >> 32) as u32),
+            updated_s: bits as u32,
+        }
+    }
+}
+
+/// Load an optional float from an atomic.
+fn load(atomic: &AtomicU32) -> Option<f32> {
+    let bits = atomic.load(Ordering::Relaxed);
+    (bits != NO_VALUE).then(|| f32::from_bits(bits))
+}
+
+/// Store an optional float into an atomic.
+fn store(atomic: &AtomicU32, value: Option<f32>) {
+    atomic.store(value.map_or(NO_VALUE, f32::to_bits), Ordering::Relaxed);
+}
@@ -0,0 +1,259 @@
+//! A driver for reading the battery supply voltage using the node's ADC.
+
+pub mod calibration;
+pub mod health;
+pub mod policy;
+pub mod protection;
+pub mod runtime;
+pub mod soc;
+pub mod source;
+
+use super::OsResult;
+use crate::re_esp;
+use calibration::Calibration;
+use esp_idf_svc::{
+    hal::{
+        adc::{
+            oneshot::{
+                config::{AdcChannelConfig, Calibration as AdcCalibration},
+                AdcChannelDriver, AdcDriver,
+            },
+            Adc, AdcChannel, Resolution,
+        },
+        gpio::ADCPin,
+    },
+    sys::{adc_atten_t, uxTaskPriorityGet, vTaskPrioritySet},
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+/// Alias for the ADC driver of the unit, that `C` belongs to
+pub type BatteryAdcDriver<C> = AdcDriver<'static, <C as AdcChannel>::AdcUnit>;
+/// Alias for the ADC channel driver
+type BatteryAdcChannelDriver<C> = AdcChannelDriver<'static, C, Arc<BatteryAdcDriver<C>>>;
+
+/// ADC channel, that the battery can be measured on.
+pub trait BatteryChannel: AdcChannel<AdcUnit: 'static> + 'static {}
+
+impl<C: AdcChannel<AdcUnit: 'static> + 'static> BatteryChannel for C {}
+
+/// Wiring of the battery measurement circuit, defined by the board profile.
+#[derive(Clone, Copy)]
+pub struct BatteryConfig {
+    /// Value of the first resistor of the voltage divider (between the battery and the ADC pin)
+    pub r1: f32,
+
+    /// Value of the second resistor of the voltage divider (between the ADC pin and ground)
+    pub r2: f32,
+
+    /// Input signal attenuation level
+    /// See the attenuation table [here](https://docs.espressif.com/projects/esp-idf/en/v4.4/esp32s3/api-reference/peripherals/adc.html#adc-attenuation).
+    pub attenuation: adc_atten_t,
+}
+
+/// Critical voltage value that's still higher than the minimum supply voltage for the ESP32
+pub const CRITICAL_VOLTAGE: f32 = 3.22;
+/// Amount of samples to read when reading ADC value.
+pub const SAMPLES: u16 = 16;
+/// Stack size of the thread measuring the voltage in the background.
+const SAMPLER_STACK_SIZE: usize = 3072;
+
+/// Battery voltage measurement driver, generic over the ADC channel of the measurement pin.
+pub struct Battery<C: BatteryChannel> {
+    /// ADC driver handle
+    adc: Arc<BatteryAdcDriver<C>>,
+
+    /// ADC channel driver handle
+    ch: BatteryAdcChannelDriver<C>,
+
+    /// Wiring of the measurement circuit
+    config: BatteryConfig,
+
+    /// Calibration of this unit, if it was calibrated
+    calibration: Option<Calibration>,
+}
+
+impl BatteryConfig {
+    /// Returns the ratio of the battery voltage to the voltage at the ADC pin.
+    pub fn divider_ratio(&self) -> f32 {
+        (self.r1 + self.r2) / self.r2
+    }
+}
+
+impl<C: BatteryChannel> Battery<C> {
+    /// Initiliaze a new instance of this driver using the given peripheral handles.
+    ///
+    /// # Errors
+    /// Returns an [`OsError::AdcInit`](crate::sysc::error::OsError::AdcInit) if the initialization of
+    /// [`BatteryAdcDriver`] or [`BatteryAdcChannelDriver`] fails.
+    pub fn new(
+        adc: impl Adc<AdcUnit = C::AdcUnit> + 'static,
+        gpio: impl ADCPin<AdcChannel = C> + 'static,
+        config: BatteryConfig,
+    ) -> OsResult<Self> {
+        let channel_config = AdcChannelConfig {
+            attenuation: config.attenuation,
+            calibration: AdcCalibration::Curve,
+            resolution: Resolution::Resolution12Bit,
+        };
+
+        let adc = Arc::new(re_esp!(BatteryAdcDriver::<C>::new(adc), AdcInit)?);
+        let ch = re_esp!(
+            BatteryAdcChannelDriver::new(Arc::clone(&adc), gpio, &channel_config),
+            AdcInit
+        )?;
+
+        Ok(Self {
+            adc,
+            ch,
+            config,
+            calibration: None,
+        })
+    }
+
+    /// Returns the ADC driver, so that other channels of the same unit can be read.
+    #[cfg(feature = "solar")]
+    pub fn adc(&self) -> Arc<BatteryAdcDriver<C>> {
+        Arc::clone(&self.adc)
+    }
+
+    /// Set the calibration to apply to the measured voltage, `None` disables it.
+    pub const fn set_calibration(&mut self, calibration: Option<Calibration>) {
+        self.calibration = calibration;
+    }
+
+    /// Returns the calibrated battery voltage.
+    ///
+    /// If no calibration was set, this is the same as [`read_uncalibrated()`](Self::read_uncalibrated).
+    ///
+    /// # Errors
+    /// Retuns an [`OsError::AdcRead`](crate::sysc::error::OsError::AdcRead)
+    /// if the ADC read operation fails.
+    pub fn read(&mut self) -> OsResult<f32> {
+        let voltage = self.read_uncalibrated()?;
+
+        Ok(self
+            .calibration
+            .map_or(voltage, |calibration| calibration.apply(voltage)))
+    }
+
+    /// Run `f`, while repeatedly measuring the calibrated battery voltage on another thread.
+    ///
+    /// This is used to measure the voltage under the load of the radio, which only draws its peak current
+    /// while it's transmitting. Returns the result of `f` and the lowest voltage measured in the meantime,
+    /// or `None` if no measurement was finished.
+    pub fn read_min_during<T>(&mut self, f: impl FnOnce() -> T) -> (T, Option<f32>)
+    where
+        Self: Send,
+    {
+        let done = AtomicBool::new(false);
+        // SAFETY: Calling a safe C function on the current task.
+        let priority = unsafe { uxTaskPriorityGet(std::ptr::null_mut()) };
+
+        thread::scope(|scope| {
+            let sampler = thread::Builder::new()
+                .stack_size(SAMPLER_STACK_SIZE)
+                .spawn_scoped(scope, || {
+                    // A busy thread with a higher priority would starve the caller, if they share a core.
+                    // With the same priority, they get time slices.
+                    // SAFETY: Calling a safe C function on the current task.
+                    unsafe { vTaskPrioritySet(std::ptr::null_mut(), priority) };
+
+                    let mut min: Option<f32> = None;
+
+                    while !done.load(Ordering::Relaxed) {
+                        match self.read() {
+                            Ok(voltage) => min = Some(min.map_or(voltage, |min| min.min(voltage))),
+                            Err(why) => {
+                                log::warn!("Failed to measure battery under load: {why}");
+                                break;
+                            }
+                        }
+                    }
+
+                    min
+                });
+
+            if let Err(why) = &sampler {
+                log::warn!("Failed to start battery sampler: {why}");
+            }
+
+            let result = f();
+            done.store(true, Ordering::Relaxed);
+
+            let min = sampler
+                .ok()
+                .and_then(|sampler| sampler.join().ok())
+                .flatten();
+            (result, min)
+        })
+    }
+
+    /// Returns the voltage measured by the ADC.
+    ///
+    /// This method internally uses multisampling to stabilize the value.
+    /// The number of samples is defined in the constant [`SAMPLES`].
+    ///
+    /// # Errors
+    /// Retuns an [`OsError::AdcRead`](crate::sysc::error::OsError::AdcRead)
+    /// if the ADC read operation fails.
+    pub fn read_uncalibrated(&mut self) -> OsResult<f32> {
+        let raw = self.read_raw_avg()?;
+        let volts = f32::from(self.raw_to_mv(raw)?) / 1000.;
+        let result = volts * self.config.divider_ratio();
+
+        Ok(result)
+    }
+
+    /// Returns a calculated average value from the ADC.
+    ///
+    /// The returned value is calculated by taking [`SAMPLES`] amount
+    /// of samples and then averaging them.
+    ///
+    /// # Errors
+    /// Retuns an [`OsError::AdcRead`](crate::sysc::error::OsError::AdcRead)
+    /// if the ADC read operation fails.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn read_raw_avg(&mut self) -> OsResult<u16> {
+        let mut avg = 0;
+
+        // NOTE: If `SAMPLES` is changed in the future, `u32` or higher
+        // must be used.
+
+        // 16 * 4095 = 65520 => within u16 range, so no need to convert to u32
+
+        for _ in 0..SAMPLES {
+            avg += self.read_raw()?;
+        }
+
+        avg /= SAMPLES;
+        Ok(avg)
+    }
+
+    /// Converts a raw ADC value into millivolts.
+    ///
+    /// This is a thin wrapper around [`raw_to_mv()`](AdcDriver::raw_to_mv)
+    /// that remaps the error to [`OsError`](crate::sysc::error::OsError).
+    ///
+    /// # Errors
+    /// Retuns an [`OsError::AdcRead`](crate::sysc::error::OsError::AdcRead) if the operation fails.
+    fn raw_to_mv(&self, raw: u16) -> OsResult<u16> {
+        re_esp!(self.adc.raw_to_mv(&self.ch, raw), AdcRead)
+    }
+
+    /// Read the ADC.
+    ///
+    /// This is a thin wrapper around [`read_raw()`](AdcDriver::read_raw)
+    /// that remaps the error to [`OsError`](crate::sysc::error::OsError).
+    ///
+    /// # Errors
+    /// Retuns an [`OsError::AdcRead`](crate::sysc::error::OsError::AdcRead) if the operation fails.
+    fn read_raw(&mut self) -> OsResult<u16> {
+        re_esp!(self.adc.read_raw(&mut self.ch), AdcRead)
+    }
+}
@@ -0,0 +1,155 @@
+//! Tiered low-battery policy.
+//!
+//! ## Tiers
+//! - [`Normal`](PowerTier::Normal): Everything runs.
+//! - [`Low`](PowerTier::Low): Below [`BATTERY_LOW_VOLTAGE`]. Extra sensors and the update check are skipped.
+//! - [`VeryLow`](PowerTier::VeryLow): Below [`BATTERY_VERY_LOW_VOLTAGE`]. Additionally, the sleep time is multiplied by
+//!   [`BATTERY_VERY_LOW_SLEEP_MULTIPLIER`] and only the main measurements are sent as a heartbeat.
+//! - [`Critical`](PowerTier::Critical): Below [`CRITICAL_VOLTAGE`]. The node goes into protective sleep, if sBOP is enabled.
+//!
+//! A tier is entered as soon as the voltage drops below its threshold, but it's only left once the voltage rises
+//! [`BATTERY_TIER_HYSTERESIS`] above it. This keeps the node from flapping between tiers, as the voltage
+//! recovers a bit after every transmission.
+//!
+//! The tier is kept in RTC memory, together with the last tier reported to the server, so that every transition
+//! is reported once.
+
+use super::CRITICAL_VOLTAGE;
+use crate::config::{
+    BATTERY_LOW_VOLTAGE, BATTERY_TIER_HYSTERESIS, BATTERY_VERY_LOW_SLEEP_MULTIPLIER,
+    BATTERY_VERY_LOW_VOLTAGE,
+};
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Thresholds of the [`Low`](PowerTier::Low), [`VeryLow`](PowerTier::VeryLow)
+/// and [`Critical`](PowerTier::Critical) tiers.
+const THRESHOLDS: [f32; 3] = [
+    BATTERY_LOW_VOLTAGE,
+    BATTERY_VERY_LOW_VOLTAGE,
+    CRITICAL_VOLTAGE,
+];
+
+/// Current tier, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static TIER: AtomicU8 = AtomicU8::new(PowerTier::Normal as u8);
+
+/// Last tier reported to the server, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static REPORTED: AtomicU8 = AtomicU8::new(PowerTier::Normal as u8);
+
+/// Power tier, ordered from the least to the most restrictive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum PowerTier {
+    /// Everything runs.
+    Normal,
+
+    /// Extra sensors and the update check are skipped.
+    Low,
+
+    /// Only a minimal heartbeat is sent, with a longer sleep time.
+    VeryLow,
+
+    /// Protective sleep.
+    Critical,
+}
+
+impl PowerTier {
+    /// Returns whether extra sensors and the update check should run.
+    pub const fn extras_enabled(self) -> bool {
+        matches!(self, Self::Normal)
+    }
+
+    /// Returns whether only a minimal heartbeat should be sent.
+    pub const fn is_minimal(self) -> bool {
+        matches!(self, Self::VeryLow | Self::Critical)
+    }
+
+    /// Returns the sleep time multiplier of the tier.
+    pub const fn sleep_multiplier(self) -> u32 {
+        if self.is_minimal() {
+            BATTERY_VERY_LOW_SLEEP_MULTIPLIER
+        } else {
+            1
+        }
+    }
+
+    /// Convert a stored value back into a tier.
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Normal,
+            1 => Self::Low,
+            2 => Self::VeryLow,
+            _ => Self::Critical,
+        }
+    }
+}
+
+impl Display for PowerTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "normal",
+            Self::Low => "low (extra sensors and updates disabled)",
+            Self::VeryLow => "very low (heartbeat only)",
+            Self::Critical => "critical (protective sleep)",
+        })
+    }
+}
+
+/// Select the tier for the battery `voltage`, and remember it.
+pub fn update(voltage: f32) -> PowerTier {
+    let tier = next_tier(current(), voltage, THRESHOLDS, BATTERY_TIER_HYSTERESIS);
+    TIER.store(tier as u8, Ordering::Relaxed);
+
+    tier
+}
+
+/// Returns the tier selected during the last [`update()`].
+pub fn current() -> PowerTier {
+    PowerTier::from_u8(TIER.load(Ordering::Relaxed))
+}
+
+/// Returns the current tier, if it has not been reported to the server yet.
+pub fn pending_report() -> Option<PowerTier> {
+    let tier = current();
+    (tier as u8 != REPORTED.load(Ordering::Relaxed)).then_some(tier)
+}
+
+/// Mark `tier` as reported to the server.
+pub fn report_sent(tier: PowerTier) {
+    REPORTED.store(tier as u8, Ordering::Relaxed);
+}
+
+/// Select the tier for `voltage`, given the `current` one.
+///
+/// `thresholds` are the descending voltages below which the [`Low`](PowerTier::Low), [`VeryLow`](PowerTier::VeryLow)
+/// and [`Critical`](PowerTier::Critical) tiers are entered. A less restrictive tier is only selected if
+/// the voltage is at least `hysteresis` above its threshold.
+pub fn next_tier(
+    current: PowerTier,
+    voltage: f32,
+    thresholds: [f32; 3],
+    hysteresis: f32,
+) -> PowerTier {
+    let tier = classify(voltage, thresholds);
+
+    if tier >= current {
+        tier
+    } else {
+        classify(voltage - hysteresis, thresholds).min(current)
+    }
+}
+
+/// Returns the tier for `voltage` without hysteresis.
+fn classify(voltage: f32, thresholds: [f32; 3]) -> PowerTier {
+    #[allow(clippy::cast_possible_truncation)]
+    let below = thresholds
+        .iter()
+        .filter(|threshold| voltage <= **threshold)
+        .count() as u8;
+
+    PowerTier::from_u8(below)
+}
@@ -0,0 +1,98 @@
+//! Protective sleep, used by sBOP when the battery is critically low.
+//!
+//! ## How it works
+//! - Instead of sleeping indefinitely, the node wakes up every [`PROTECTIVE_SLEEP_CHECK_INTERVAL`].
+//! - The battery is measured right after boot, before the radio or any sensor is started.
+//! - If the battery is still in the [`Critical`](PowerTier::Critical) tier, the node goes straight back to sleep.
+//!   The sleep itself is left to `main`, so that the wake-up sources are armed and the cycle is recorded as usual.
+//!   Otherwise it continues normally, and the time spent offline is reported to the server.
+//!
+//! The tier is selected by the [policy](super::policy), so the battery must recover past the hysteresis threshold.
+//! The protective sleep state is kept in RTC memory, so it survives deep sleep, but not a power loss.
+
+use super::{
+    policy::{self, PowerTier},
+    source::BatterySource,
+};
+use crate::{
+    config::PROTECTIVE_SLEEP_CHECK_INTERVAL,
+    sysc::{rtc::RtcCell, schedule::now_us, usbctl},
+};
+use std::time::Duration;
+
+/// Protective sleep state, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static STATE: RtcCell<State> = RtcCell::new(State::new());
+
+/// Protective sleep state.
+#[derive(Clone, Copy)]
+struct State {
+    /// System time when the protective sleep was entered, in microseconds.
+    since_us: Option<u64>,
+
+    /// Time spent in protective sleep, if it was not reported yet.
+    offline: Option<Duration>,
+}
+
+/// Put the node into protective sleep.
+///
+/// Returns how long to sleep, until the battery is checked again.
+pub fn enter() -> Duration {
+    STATE.update(|state| {
+        state.since_us.get_or_insert_with(now_us);
+    });
+
+    log::warn!(
+        "Entering protective sleep, checking the battery every {PROTECTIVE_SLEEP_CHECK_INTERVAL:?}"
+    );
+    PROTECTIVE_SLEEP_CHECK_INTERVAL
+}
+
+/// Check the battery, if the node is in protective sleep.
+///
+/// Returns how long to sleep if the battery has not recovered yet, or `None` if the boot should continue.
+pub fn check(source: &mut impl BatterySource) -> Option<Duration> {
+    let since_us = STATE.get().since_us?;
+
+    if usbctl::is_connected() {
+        log::info!("USB is connected, leaving protective sleep");
+    } else {
+        match source.read_voltage() {
+            Ok(voltage) if policy::update(voltage) == PowerTier::Critical => {
+                log::debug!("Battery has not recovered yet ({voltage:.02}V)");
+                return Some(PROTECTIVE_SLEEP_CHECK_INTERVAL);
+            }
+            Ok(voltage) => log::info!("Battery has recovered ({voltage:.02}V)"),
+            Err(why) => {
+                log::warn!("Failed to measure battery during protective sleep: {why}");
+                return Some(PROTECTIVE_SLEEP_CHECK_INTERVAL);
+            }
+        }
+    }
+
+    STATE.update(|state| {
+        state.since_us = None;
+        state.offline = Some(Duration::from_micros(now_us().saturating_sub(since_us)));
+    });
+
+    None
+}
+
+/// Returns how long the node was in protective sleep, if it was not reported yet.
+pub fn pending_report() -> Option<Duration> {
+    STATE.get().offline
+}
+
+/// Mark the time spent in protective sleep as reported.
+pub fn report_sent() {
+    STATE.update(|state| state.offline = None);
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            since_us: None,
+            offline: None,
+        }
+    }
+}
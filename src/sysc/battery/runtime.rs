@@ -0,0 +1,204 @@
+//! Remaining battery runtime prediction.
+//!
+//! ## How it works
+//! - A sample of the voltage at rest is stored in RTC memory every [`BATTERY_HISTORY_INTERVAL`], keeping the last [`HISTORY_LEN`] samples.
+//! - A line is fitted through the samples using least squares. The time when it reaches [`CRITICAL_VOLTAGE`]
+//!   is the predicted end of the runtime.
+//! - The drain depends on how often the node wakes up, so the history is cleared when the sleep time changes.
+//!   It's also cleared when the voltage jumps up, since that means the battery was charged or replaced,
+//!   and when the system time jumps (e.g. when it's first synchronized).
+//!
+//! The history survives deep sleep, but not a power loss. A prediction is only made once the history spans
+//! at least [`MIN_SPAN`], as the voltage of Li-ion cells barely changes in the short term.
+
+use super::CRITICAL_VOLTAGE;
+use crate::{
+    config::{BATTERY_HISTORY_INTERVAL, BATTERY_RUNTIME_WARNING_DAYS},
+    sysc::{rtc::RtcCell, schedule::now_us},
+};
+use std::time::Duration;
+
+/// Maximum number of samples kept.
+const HISTORY_LEN: usize = 48;
+/// Minimum number of samples required for a prediction.
+const MIN_SAMPLES: usize = 4;
+/// Minimum time covered by the samples required for a prediction.
+const MIN_SPAN: Duration = Duration::from_hours(24);
+/// Voltage increase, that's considered a charge or a battery replacement.
+const CHARGE_THRESHOLD: f32 = 0.1;
+/// Gap between two samples, after which the history is considered stale (e.g. the time was synchronized).
+const MAX_GAP: Duration = Duration::from_hours(24 * 7);
+/// Seconds in a day.
+const SECS_PER_DAY: f64 = 86_400.0;
+
+/// Voltage history, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static STATE: RtcCell<State> = RtcCell::new(State::new());
+
+/// A single voltage sample.
+#[derive(Clone, Copy)]
+pub struct Sample {
+    /// System time of the sample in seconds.
+    pub time_s: u32,
+
+    /// Battery voltage in millivolts.
+    pub millivolts: u16,
+}
+
+/// Runtime prediction state.
+#[derive(Clone, Copy)]
+struct State {
+    /// Samples sorted by time, only the first `len` are valid.
+    samples: [Sample; HISTORY_LEN],
+
+    /// Number of valid samples.
+    len: usize,
+
+    /// Sleep time the samples were taken with, in seconds.
+    sleep_time_s: u64,
+
+    /// Whether the low runtime warning was already sent.
+    warned: bool,
+}
+
+/// Record the battery voltage and predict the remaining runtime in days.
+///
+/// Returns `None` if there is not enough history, or if the battery is not discharging.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn update(voltage: f32, sleep_time: Duration) -> Option<f32> {
+    let now_s = (now_us() / 1_000_000) as u32;
+    let millivolts = (voltage * 1000.0).round() as u16;
+
+    STATE.update(|state| {
+        let last = state.samples[..state.len].last().copied();
+
+        if state.sleep_time_s != sleep_time.as_secs() {
+            if state.len != 0 {
+                log::debug!("Sleep time has changed, clearing voltage history");
+            }
+            state.clear(sleep_time);
+        } else if last.is_some_and(|last| {
+            f32::from(millivolts.saturating_sub(last.millivolts)) / 1000.0 >= CHARGE_THRESHOLD
+        }) {
+            log::debug!("Battery was charged, clearing voltage history");
+            state.clear(sleep_time);
+        } else if last.is_some_and(|last| {
+            now_s < last.time_s || u64::from(now_s - last.time_s) > MAX_GAP.as_secs()
+        }) {
+            log::debug!("System time has jumped, clearing voltage history");
+            state.clear(sleep_time);
+        } else if last.is_some_and(|last| {
+            u64::from(now_s.saturating_sub(last.time_s)) < BATTERY_HISTORY_INTERVAL.as_secs()
+        }) {
+            return;
+        }
+
+        state.push(Sample {
+            time_s: now_s,
+            millivolts,
+        });
+    });
+
+    let state = STATE.get();
+    predict(&state.samples[..state.len], CRITICAL_VOLTAGE)
+}
+
+/// Returns whether the low runtime warning should be sent for the predicted `days`.
+pub fn warning_due(days: Option<f32>) -> bool {
+    days.is_some_and(|days| days <= BATTERY_RUNTIME_WARNING_DAYS) && !STATE.get().warned
+}
+
+/// Mark the low runtime warning as sent.
+///
+/// It will not be sent again until the battery is charged.
+pub fn warning_sent() {
+    STATE.update(|state| state.warned = true);
+}
+
+/// Predict the days left until the voltage reaches `critical_voltage`.
+///
+/// Returns `None` if the samples do not span [`MIN_SPAN`], or if the voltage is not decreasing.
+#[allow(clippy::cast_possible_truncation)]
+pub fn predict(samples: &[Sample], critical_voltage: f32) -> Option<f32> {
+    let (first, last) = (samples.first()?, samples.last()?);
+
+    if samples.len() < MIN_SAMPLES
+        || u64::from(last.time_s.saturating_sub(first.time_s)) < MIN_SPAN.as_secs()
+    {
+        return None;
+    }
+
+    let (slope, intercept) = fit(samples)?;
+    if slope >= 0.0 {
+        return None;
+    }
+
+    // time is relative to the first sample
+    let now = f64::from(last.time_s - first.time_s);
+    let critical_mv = f64::from(critical_voltage) * 1000.0;
+    let end = (critical_mv - intercept) / slope;
+
+    Some(((end - now) / SECS_PER_DAY).max(0.0) as f32)
+}
+
+/// Fit a line through the samples using least squares.
+///
+/// Returns the slope in millivolts per second and the intercept in millivolts, with time relative to the first sample.
+/// Returns `None` if all samples were taken at the same time.
+#[allow(clippy::cast_precision_loss, clippy::similar_names)]
+pub fn fit(samples: &[Sample]) -> Option<(f64, f64)> {
+    let first = samples.first()?;
+    let n = samples.len() as f64;
+
+    let (sum_t, sum_v, sum_tt, sum_tv) =
+        samples
+            .iter()
+            .fold((0.0, 0.0, 0.0, 0.0), |(st, sv, stt, stv), sample| {
+                let t = f64::from(sample.time_s - first.time_s);
+                let v = f64::from(sample.millivolts);
+
+                (st + t, sv + v, t.mul_add(t, stt), t.mul_add(v, stv))
+            });
+
+    let denominator = n.mul_add(sum_tt, -(sum_t * sum_t));
+    if denominator == 0.0 {
+        return None;
+    }
+
+    let slope = n.mul_add(sum_tv, -(sum_t * sum_v)) / denominator;
+    let intercept = slope.mul_add(-sum_t, sum_v) / n;
+
+    Some((slope, intercept))
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            samples: [Sample {
+                time_s: 0,
+                millivolts: 0,
+            }; HISTORY_LEN],
+            len: 0,
+            sleep_time_s: 0,
+            warned: false,
+        }
+    }
+
+    /// Remove all samples and start a new history with `sleep_time`.
+    const fn clear(&mut self, sleep_time: Duration) {
+        self.len = 0;
+        self.sleep_time_s = sleep_time.as_secs();
+        self.warned = false;
+    }
+
+    /// Add a sample, dropping the oldest one if the history is full.
+    const fn push(&mut self, sample: Sample) {
+        if self.len == HISTORY_LEN {
+            self.samples.rotate_left(1);
+            self.len -= 1;
+        }
+
+        self.samples[self.len] = sample;
+        self.len += 1;
+    }
+}
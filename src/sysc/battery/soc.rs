@@ -0,0 +1,88 @@
+//! Battery state-of-charge estimation.
+//!
+//! The state of charge is estimated from the battery voltage, using the discharge curve from `sys.rs`.
+//! The voltage of Li-ion cells drops in the cold, so it's compensated using the environment temperature first.
+//! The voltage is measured at rest, before the radio is started, as it would otherwise sag under its load.
+//! Single readings are still noisy, so the estimate is smoothed across wake-ups using an exponential
+//! moving average kept in RTC memory.
+
+use crate::config::{BATTERY_DISCHARGE_CURVE, BATTERY_SOC_SMOOTHING, BATTERY_TEMP_COEFFICIENT};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Temperature at which the discharge curve is valid.
+const REFERENCE_TEMPERATURE: f32 = 25.0;
+/// Marks [`SMOOTHED_SOC`] as empty.
+const NO_SOC: u32 = u32::MAX;
+
+/// Bits of the smoothed state of charge, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static SMOOTHED_SOC: AtomicU32 = AtomicU32::new(NO_SOC);
+
+/// Estimate the state of charge in percent from the battery voltage and the ambient temperature.
+///
+/// The estimate is smoothed using the value from the previous wake-ups.
+pub fn estimate(voltage: f32, temperature: f32) -> f32 {
+    let raw = from_voltage(
+        compensate(voltage, temperature, BATTERY_TEMP_COEFFICIENT),
+        BATTERY_DISCHARGE_CURVE,
+    );
+
+    let previous = SMOOTHED_SOC.load(Ordering::Relaxed);
+    let soc = if previous == NO_SOC {
+        raw
+    } else {
+        smooth(f32::from_bits(previous), raw, BATTERY_SOC_SMOOTHING)
+    };
+
+    SMOOTHED_SOC.store(soc.to_bits(), Ordering::Relaxed);
+    soc
+}
+
+/// Convert the voltage measured at `temperature` into the voltage expected at the reference temperature.
+///
+/// `coefficient` is the drop of the voltage per *C below the reference temperature.
+/// Higher temperatures are not compensated, since their effect is negligible.
+pub fn compensate(voltage: f32, temperature: f32, coefficient: f32) -> f32 {
+    (REFERENCE_TEMPERATURE - temperature)
+        .max(0.0)
+        .mul_add(coefficient, voltage)
+}
+
+/// Map a voltage to the state of charge in percent, by interpolating between the points of `curve`.
+///
+/// `curve` contains (voltage, percent) points sorted by voltage. Voltages outside of the curve are clamped.
+pub fn from_voltage(voltage: f32, curve: &[(f32, f32)]) -> f32 {
+    let (Some(&(min_v, min_soc)), Some(&(max_v, max_soc))) = (curve.first(), curve.last()) else {
+        return 0.0;
+    };
+
+    if voltage <= min_v {
+        return min_soc;
+    }
+
+    if voltage >= max_v {
+        return max_soc;
+    }
+
+    curve
+        .windows(2)
+        .find(|points| voltage <= points[1].0)
+        .map_or(max_soc, |points| {
+            let ((v0, soc0), (v1, soc1)) = (points[0], points[1]);
+
+            if v1 <= v0 {
+                return soc1;
+            }
+
+            ((voltage - v0) / (v1 - v0)).mul_add(soc1 - soc0, soc0)
+        })
+}
+
+/// Combine the previous estimate with a new one, using an exponential moving average.
+///
+/// `factor` is the weight of the new estimate (`0.0..=1.0`).
+pub fn smooth(previous: f32, new: f32, factor: f32) -> f32 {
+    let factor = factor.clamp(0.0, 1.0);
+
+    (new - previous).mul_add(factor, previous)
+}
@@ -0,0 +1,140 @@
+//! Sources of battery measurements.
+//!
+//! Most boards measure the battery voltage using the ADC and a voltage divider ([`Battery`]).
+//! Some boards (e.g. the Adafruit Feather ESP32-S3) have an I2C fuel gauge instead, which is far more
+//! accurate and also tracks the state of charge on its own. The board profile selects the source using [`FuelGauge`].
+
+use super::{Battery, BatteryChannel};
+use crate::{
+    config::FUEL_GAUGE_CAPACITY,
+    sysc::{
+        ext_drivers::{Lc709203f, Max17048},
+        OsResult,
+    },
+};
+use esp_idf_svc::hal::i2c::I2cDriver;
+
+/// Fuel gauges, that can be used instead of the ADC measurement.
+// none of the built-in board profiles has a fuel gauge
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum FuelGauge {
+    /// Analog Devices MAX17048
+    Max17048,
+
+    /// onsemi LC709203F
+    Lc709203f,
+}
+
+/// A single reading of a battery source.
+#[derive(Clone, Copy, Debug)]
+pub struct BatteryReading {
+    /// Battery voltage
+    pub voltage: f32,
+
+    /// State of charge in percent, if the source tracks it
+    pub soc: Option<f32>,
+
+    /// Charge (positive) or discharge (negative) rate in percent per hour, if the source measures it
+    pub charge_rate: Option<f32>,
+}
+
+/// Contains functionality that a battery source must be able to do.
+pub trait BatterySource {
+    /// Read the battery voltage.
+    ///
+    /// # Errors
+    /// Upon a measurement or communication error, an `Err(..)` value will be returned.
+    fn read_voltage(&mut self) -> OsResult<f32>;
+
+    /// Read the state of charge in percent. If the source does not track it, `Ok(None)` will be returned.
+    ///
+    /// # Errors
+    /// Upon a communication error, an `Err(..)` value will be returned.
+    fn read_soc(&mut self) -> OsResult<Option<f32>>;
+
+    /// Read the charge rate in percent per hour. If the source does not measure it, `Ok(None)` will be returned.
+    ///
+    /// # Errors
+    /// Upon a communication error, an `Err(..)` value will be returned.
+    fn read_charge_rate(&mut self) -> OsResult<Option<f32>>;
+
+    /// Read everything the source supports.
+    ///
+    /// # Errors
+    /// Upon a measurement or communication error, an `Err(..)` value will be returned.
+    fn read_all(&mut self) -> OsResult<BatteryReading> {
+        Ok(BatteryReading {
+            voltage: self.read_voltage()?,
+            soc: self.read_soc()?,
+            charge_rate: self.read_charge_rate()?,
+        })
+    }
+}
+
+/// A wrapper that allows abstracting the battery source without the use of generics.
+pub enum AnyBatterySource<'s, C: BatteryChannel> {
+    Adc(&'s mut Battery<C>),
+    Max17048(Max17048<'s>),
+    Lc709203f(Lc709203f<'s>),
+}
+
+impl<'s, C: BatteryChannel> AnyBatterySource<'s, C> {
+    /// Select the source using the fuel gauge of the board, or the ADC if it has none.
+    ///
+    /// # Errors
+    /// Returns an error if the fuel gauge does not respond.
+    pub fn new(
+        fuel_gauge: Option<FuelGauge>,
+        battery: &'s mut Battery<C>,
+        i2c: &'s mut I2cDriver<'static>,
+    ) -> OsResult<Self> {
+        Ok(match fuel_gauge {
+            None => Self::Adc(battery),
+            Some(FuelGauge::Max17048) => Self::Max17048(Max17048::new_with_driver(i2c)?),
+            Some(FuelGauge::Lc709203f) => {
+                Self::Lc709203f(Lc709203f::new_with_driver(i2c, FUEL_GAUGE_CAPACITY)?)
+            }
+        })
+    }
+}
+
+impl<C: BatteryChannel> BatterySource for Battery<C> {
+    fn read_voltage(&mut self) -> OsResult<f32> {
+        self.read()
+    }
+
+    fn read_soc(&mut self) -> OsResult<Option<f32>> {
+        Ok(None)
+    }
+
+    fn read_charge_rate(&mut self) -> OsResult<Option<f32>> {
+        Ok(None)
+    }
+}
+
+impl<C: BatteryChannel> BatterySource for AnyBatterySource<'_, C> {
+    fn read_voltage(&mut self) -> OsResult<f32> {
+        match self {
+            Self::Adc(dev) => dev.read_voltage(),
+            Self::Max17048(dev) => dev.read_voltage(),
+            Self::Lc709203f(dev) => dev.read_voltage(),
+        }
+    }
+
+    fn read_soc(&mut self) -> OsResult<Option<f32>> {
+        match self {
+            Self::Adc(dev) => dev.read_soc(),
+            Self::Max17048(dev) => dev.read_soc(),
+            Self::Lc709203f(dev) => dev.read_soc(),
+        }
+    }
+
+    fn read_charge_rate(&mut self) -> OsResult<Option<f32>> {
+        match self {
+            Self::Adc(dev) => dev.read_charge_rate(),
+            Self::Max17048(dev) => dev.read_charge_rate(),
+            Self::Lc709203f(dev) => dev.read_charge_rate(),
+        }
+    }
+}
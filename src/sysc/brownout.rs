@@ -1,9 +1,50 @@
 //! Brownout management driver.
 //!
-//! Currently this module only allows disabling the brownout protection feature.
+//! ## How it works
+//! - The threshold of the brownout detector can be changed at runtime ([`set_threshold()`]), or the detector can be
+//!   disabled completely in debug builds.
+//! - Brownout resets are counted in NVS, since RTC memory is reinitialized after them. The count is reported
+//!   to the server, and cleared once it's sent.
+//! - For [`BROWNOUT_RECOVERY_CYCLES`] wake cycles after a brownout reset, the node is [recovering](recovering):
+//!   the voltage is left to settle before the radio is started, the transmit power is lowered and
+//!   firmware updates are skipped, to avoid current spikes that could cause another brownout.
 
-use esp_idf_svc::sys::RTC_CNTL_BROWN_OUT_REG;
-use std::ptr;
+use super::{nvs::NonVolatileStorage, power::ResetReason, OsError, OsResult, ReportableError};
+use crate::config::BROWNOUT_RECOVERY_CYCLES;
+use esp_idf_svc::sys::{
+    RTC_CNTL_BROWN_OUT_ENA, RTC_CNTL_BROWN_OUT_PD_RF_ENA, RTC_CNTL_BROWN_OUT_REG,
+    RTC_CNTL_BROWN_OUT_RST_ENA,
+};
+use std::{
+    ops::RangeInclusive,
+    ptr,
+    sync::atomic::{AtomicU32, AtomicU8, Ordering},
+};
+
+/// Internal analog I2C block of the brownout detector.
+const I2C_BOD: u8 = 0x61;
+/// Host ID of [`I2C_BOD`].
+const I2C_BOD_HOSTID: u8 = 1;
+/// Register of the brownout threshold.
+const I2C_BOD_THRESHOLD: u8 = 0x5;
+/// Most significant bit of the threshold in [`I2C_BOD_THRESHOLD`].
+const I2C_BOD_THRESHOLD_MSB: u8 = 2;
+/// Least significant bit of the threshold in [`I2C_BOD_THRESHOLD`].
+const I2C_BOD_THRESHOLD_LSB: u8 = 0;
+/// Valid threshold levels, from the highest voltage to the lowest.
+const LEVELS: RangeInclusive<u8> = 1..=7;
+
+/// Wake cycles left until the node has recovered from a brownout, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static RECOVERY: AtomicU8 = AtomicU8::new(0);
+
+/// Number of brownout resets, that were not reported yet.
+static UNREPORTED: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    /// Write bits of an internal analog I2C register (ROM function).
+    fn esp_rom_regi2c_write_mask(block: u8, host_id: u8, reg_add: u8, msb: u8, lsb: u8, data: u8);
+}
 
 /// Disable the brownout detector by zeroing the brownout control register ([`RTC_CNTL_BROWN_OUT_REG`].)
 pub fn disable_brownout_detector() {
@@ -11,3 +52,83 @@ pub fn disable_brownout_detector() {
         ptr::write_volatile(RTC_CNTL_BROWN_OUT_REG as *mut i32, 0);
     }
 }
+
+/// Set the threshold level of the brownout detector, and enable it with a reset.
+///
+/// Levels go from `1` (around 3.3V) to `7` (around 2.4V), higher levels trigger at a lower voltage.
+/// See the `ESP_BROWNOUT_DET_LVL_SEL_*` options of the ESP-IDF for the exact voltages.
+///
+/// # Errors
+/// Returns [`OsError::IllegalBrownoutLevel`] if the level is out of range.
+pub fn set_threshold(level: u8) -> OsResult<()> {
+    if !LEVELS.contains(&level) {
+        return Err(OsError::IllegalBrownoutLevel(level));
+    }
+
+    // SAFETY: The register is only modified here, the wait times configured by the ESP-IDF are kept.
+    unsafe {
+        esp_rom_regi2c_write_mask(
+            I2C_BOD,
+            I2C_BOD_HOSTID,
+            I2C_BOD_THRESHOLD,
+            I2C_BOD_THRESHOLD_MSB,
+            I2C_BOD_THRESHOLD_LSB,
+            level,
+        );
+
+        let control = ptr::read_volatile(RTC_CNTL_BROWN_OUT_REG as *const u32);
+        ptr::write_volatile(
+            RTC_CNTL_BROWN_OUT_REG as *mut u32,
+            control
+                | RTC_CNTL_BROWN_OUT_ENA
+                | RTC_CNTL_BROWN_OUT_RST_ENA
+                | RTC_CNTL_BROWN_OUT_PD_RF_ENA,
+        );
+    }
+
+    log::debug!("Brownout threshold set to level {level}");
+    Ok(())
+}
+
+/// Count the reset if it was caused by a brownout, and start or continue the recovery.
+pub fn check(nvs: &NonVolatileStorage, reason: ResetReason) {
+    let mut count = nvs
+        .get_brownout_count()
+        .inspect_err(|why| log::warn!("Failed to read brownout count: {why}"))
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+
+    if reason == ResetReason::Brownout {
+        log::warn!("Recovering from a brownout reset");
+        count = count.saturating_add(1);
+        RECOVERY.store(BROWNOUT_RECOVERY_CYCLES, Ordering::Relaxed);
+
+        nvs.store_brownout_count(count)
+            .report("Failed to store brownout count");
+    } else {
+        RECOVERY.store(
+            RECOVERY.load(Ordering::Relaxed).saturating_sub(1),
+            Ordering::Relaxed,
+        );
+    }
+
+    UNREPORTED.store(count, Ordering::Relaxed);
+}
+
+/// Returns whether the node is recovering from a recent brownout reset.
+pub fn recovering() -> bool {
+    RECOVERY.load(Ordering::Relaxed) != 0
+}
+
+/// Returns the number of brownout resets, if there are any that were not reported yet.
+pub fn pending_report() -> Option<u32> {
+    Some(UNREPORTED.load(Ordering::Relaxed)).filter(|count| *count != 0)
+}
+
+/// Mark the brownout resets as reported.
+pub fn report_sent(nvs: &NonVolatileStorage) {
+    UNREPORTED.store(0, Ordering::Relaxed);
+    nvs.clear_brownout_count()
+        .report("Failed to clear brownout count");
+}
@@ -0,0 +1,101 @@
+//! Wake-up button.
+//!
+//! The button of the board ([`WAKE_BUTTON`]) wakes the node up from deep sleep (*ext1* wake-up, shared with the pulse inputs):
+//! - A short press triggers an immediate measurement, which is reported like a regular one.
+//! - Holding it for [`WAKE_BUTTON_LONG_PRESS`] enters maintenance mode. The node stays awake with the LED on,
+//!   and accepts [console](super::console) commands over USB until the `exit` command.
+//!
+//! All *ext1* pins share the same trigger level, so an active-high button cannot be used together with the pulse inputs.
+
+use super::{
+    periph::WAKE_BUTTON,
+    power::{get_wakeup_cause, WakeupCause},
+    OsResult,
+};
+use crate::{config::WAKE_BUTTON_LONG_PRESS, re_esp};
+use esp_idf_svc::sys::{
+    esp, esp_sleep_enable_ext1_wakeup_io, esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_HIGH,
+    esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_LOW, rtc_gpio_get_level, rtc_gpio_init,
+    rtc_gpio_mode_t_RTC_GPIO_MODE_INPUT_ONLY, rtc_gpio_set_direction,
+};
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Interval of checking whether the button has been released.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Ways of pressing the button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Press {
+    /// Released before [`WAKE_BUTTON_LONG_PRESS`]
+    Short,
+
+    /// Held for at least [`WAKE_BUTTON_LONG_PRESS`]
+    Long,
+}
+
+/// Enable waking up on a button press during the next deep sleep.
+///
+/// # Errors
+/// Returns an error if the wake-up source cannot be configured.
+pub fn arm() -> OsResult<()> {
+    let mode = if WAKE_BUTTON.active_low {
+        esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_LOW
+    } else {
+        esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_HIGH
+    };
+
+    // SAFETY: Calling a safe C function.
+    re_esp!(
+        esp!(unsafe { esp_sleep_enable_ext1_wakeup_io(1 << WAKE_BUTTON.pin, mode) }),
+        GpioInit
+    )
+}
+
+/// Find out how the button is being pressed, if it has woken the MCU up.
+///
+/// This blocks until the button is released, or for up to [`WAKE_BUTTON_LONG_PRESS`].
+///
+/// # Errors
+/// Returns an error if the button's pin cannot be configured.
+pub fn handle_wakeup() -> OsResult<Option<Press>> {
+    if get_wakeup_cause() != WakeupCause::Button {
+        return Ok(None);
+    }
+
+    // SAFETY: The pin is not used by anything else.
+    unsafe {
+        re_esp!(esp!(rtc_gpio_init(WAKE_BUTTON.pin)), GpioInit)?;
+        re_esp!(
+            esp!(rtc_gpio_set_direction(
+                WAKE_BUTTON.pin,
+                rtc_gpio_mode_t_RTC_GPIO_MODE_INPUT_ONLY
+            )),
+            GpioInit
+        )?;
+    }
+
+    let start = Instant::now();
+    while is_pressed() && start.elapsed() < WAKE_BUTTON_LONG_PRESS {
+        sleep(POLL_INTERVAL);
+    }
+
+    let press = if start.elapsed() >= WAKE_BUTTON_LONG_PRESS {
+        Press::Long
+    } else {
+        Press::Short
+    };
+
+    log::info!("Woken up by a {press:?} button press");
+    Ok(Some(press))
+}
+
+/// Returns whether the button is being pressed.
+fn is_pressed() -> bool {
+    // SAFETY: Calling a safe C function.
+    let high = unsafe { rtc_gpio_get_level(WAKE_BUTTON.pin) } != 0;
+
+    high != WAKE_BUTTON.active_low
+}
@@ -0,0 +1,70 @@
+//! Additional measurement channels.
+//!
+//! [`PwmpClient::post_measurements()`] only carries the readings of the main environment sensor
+//! and the node's own statistics. Readings from auxiliary sensors are collected here and
+//! delivered to the server as a single notification after the main measurements were posted.
+
+use super::OsResult;
+use pwmp_client::PwmpClient;
+use std::fmt::Display;
+
+/// Collection of named readings from auxiliary sensors.
+#[derive(Default)]
+pub struct Channels(Vec<Channel>);
+
+/// A single named reading.
+struct Channel {
+    /// Unique name of the channel.
+    name: String,
+
+    /// The measured value.
+    value: f32,
+
+    /// Unit of the value, may be empty.
+    unit: &'static str,
+}
+
+impl Channels {
+    /// Add a new reading.
+    pub fn push(&mut self, name: impl Into<String>, value: f32, unit: &'static str) {
+        let name = name.into();
+        log::debug!("Channel {name}: {value:.02}{unit}");
+
+        self.0.push(Channel { name, value, unit });
+    }
+
+    /// Returns whether no readings were added.
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Send all readings to the PWMP server.
+    ///
+    /// Nothing is sent if there are no readings.
+    ///
+    /// # Errors
+    /// Returns an error if sending the notification fails.
+    pub fn send(&self, pws: &mut PwmpClient) -> OsResult<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        pws.send_notification(format!("Channels: {self}"))?;
+        Ok(())
+    }
+}
+
+impl Display for Channels {
+    /// Formats the readings as a comma-separated list of `name=value` pairs.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, channel) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+
+            write!(f, "{}={:.02}{}", channel.name, channel.value, channel.unit)?;
+        }
+
+        Ok(())
+    }
+}
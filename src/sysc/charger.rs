@@ -0,0 +1,184 @@
+//! Solar charger monitoring.
+//!
+//! Supports TP4056-style chargers, which signal their state on two open-drain status pins:
+//! - `CHRG` is pulled low while the battery is charging.
+//! - `STDBY` is pulled low when the battery is fully charged.
+//! - Both are released if there is no input power.
+//!
+//! The voltage of the solar panel is measured through a voltage divider on a second channel of the battery's ADC.
+//! While the panel provides enough power, the node can afford to wake up more often (see [`adjust_sleep_time()`]).
+//!
+//! Charging is disabled using the charger's `CE` input, while the temperature is outside the safe range
+//! (see [`thermal`](super::thermal)).
+
+use super::{
+    battery::{BatteryAdcDriver, BatteryChannel, BatteryConfig, SAMPLES},
+    periph::SolarPeripherals,
+    thermal, OsResult,
+};
+use crate::{
+    config::{SOLAR_CHARGING_SLEEP_DIVISOR, SOLAR_DAYLIGHT_VOLTAGE},
+    re_esp,
+};
+use esp_idf_svc::{
+    hal::{
+        adc::{
+            oneshot::{
+                config::{AdcChannelConfig, Calibration},
+                AdcChannelDriver,
+            },
+            Resolution,
+        },
+        gpio::{ADCPin, Input, Output, PinDriver, Pull},
+    },
+    sys::{esp, gpio_hold_dis},
+};
+use std::{sync::Arc, time::Duration};
+
+/// State reported by the charger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChargeState {
+    /// No input power, e.g. at night.
+    NoInput,
+
+    /// The battery is being charged.
+    Charging,
+
+    /// The battery is fully charged.
+    Charged,
+}
+
+/// State of the charger and the solar panel.
+#[derive(Clone, Copy, Debug)]
+pub struct ChargeStatus {
+    /// State reported by the charger
+    pub state: ChargeState,
+
+    /// Voltage of the solar panel
+    pub panel_voltage: f32,
+}
+
+/// Solar charger driver.
+pub struct Charger<C: BatteryChannel> {
+    /// ADC driver handle, shared with the battery
+    adc: Arc<BatteryAdcDriver<C>>,
+
+    /// ADC channel driver handle for the panel voltage
+    ch: AdcChannelDriver<'static, C, Arc<BatteryAdcDriver<C>>>,
+
+    /// `CHRG` status pin
+    chrg: PinDriver<'static, Input>,
+
+    /// `STDBY` status pin
+    stdby: PinDriver<'static, Input>,
+
+    /// `CE` input of the charger
+    enable: PinDriver<'static, Output>,
+
+    /// Voltage divider of the panel voltage measurement
+    divider: BatteryConfig,
+}
+
+impl<C: BatteryChannel> Charger<C> {
+    /// Initialize the driver using the ADC driver of the battery.
+    ///
+    /// # Errors
+    /// Returns an error if the ADC channel or the status pins cannot be set up.
+    pub fn new(
+        adc: Arc<BatteryAdcDriver<C>>,
+        peripherals: SolarPeripherals<impl ADCPin<AdcChannel = C> + 'static>,
+    ) -> OsResult<Self> {
+        let config = AdcChannelConfig {
+            attenuation: peripherals.divider.attenuation,
+            calibration: Calibration::Curve,
+            resolution: Resolution::Resolution12Bit,
+        };
+
+        let ch = re_esp!(
+            AdcChannelDriver::new(Arc::clone(&adc), peripherals.panel, &config),
+            AdcInit
+        )?;
+
+        // the status outputs are open-drain
+        let mut chrg = re_esp!(PinDriver::input(peripherals.chrg), GpioInit)?;
+        re_esp!(chrg.set_pull(Pull::Up), GpioInit)?;
+        let mut stdby = re_esp!(PinDriver::input(peripherals.stdby), GpioInit)?;
+        re_esp!(stdby.set_pull(Pull::Up), GpioInit)?;
+
+        // the pin is held during deep sleep, it's only released once the same level is set again
+        let mut enable = re_esp!(PinDriver::output(peripherals.enable), GpioInit)?;
+        re_esp!(
+            enable.set_level(thermal::charging_allowed().into()),
+            GpioInit
+        )?;
+        // SAFETY: The pin is owned by this driver.
+        re_esp!(esp!(unsafe { gpio_hold_dis(enable.pin()) }), GpioInit)?;
+
+        Ok(Self {
+            adc,
+            ch,
+            chrg,
+            stdby,
+            enable,
+            divider: peripherals.divider,
+        })
+    }
+
+    /// Enable or disable charging.
+    ///
+    /// # Errors
+    /// Returns an error if the level of the `CE` pin cannot be set.
+    pub fn set_charging(&mut self, enabled: bool) -> OsResult<()> {
+        re_esp!(self.enable.set_level(enabled.into()), GpioInit)
+    }
+
+    /// Read the charger state and the panel voltage.
+    ///
+    /// # Errors
+    /// Returns an error if the ADC read operation fails.
+    pub fn read(&mut self) -> OsResult<ChargeStatus> {
+        let state = if self.chrg.is_low() {
+            ChargeState::Charging
+        } else if self.stdby.is_low() {
+            ChargeState::Charged
+        } else {
+            ChargeState::NoInput
+        };
+
+        Ok(ChargeStatus {
+            state,
+            panel_voltage: self.read_panel_voltage()?,
+        })
+    }
+
+    /// Returns the voltage of the solar panel.
+    fn read_panel_voltage(&mut self) -> OsResult<f32> {
+        let mut sum = 0u32;
+
+        for _ in 0..SAMPLES {
+            sum += u32::from(re_esp!(self.adc.read_raw(&mut self.ch), AdcRead)?);
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let raw = (sum / u32::from(SAMPLES)) as u16;
+        let millivolts = re_esp!(self.adc.raw_to_mv(&self.ch, raw), AdcRead)?;
+
+        Ok(f32::from(millivolts) / 1000. * self.divider.divider_ratio())
+    }
+}
+
+impl ChargeStatus {
+    /// Returns whether the panel provides enough power to sample more often.
+    pub fn has_surplus(self) -> bool {
+        self.state != ChargeState::NoInput && self.panel_voltage >= SOLAR_DAYLIGHT_VOLTAGE
+    }
+}
+
+/// Shorten the sleep time while the panel provides enough power.
+pub fn adjust_sleep_time(sleep_time: Duration, status: ChargeStatus) -> Duration {
+    if status.has_surplus() && SOLAR_CHARGING_SLEEP_DIVISOR > 1 {
+        sleep_time / SOLAR_CHARGING_SLEEP_DIVISOR
+    } else {
+        sleep_time
+    }
+}
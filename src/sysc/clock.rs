@@ -0,0 +1,155 @@
+//! Wall-clock time and sleep time compensation.
+//!
+//! ## Runtime compensation
+//! The sleep time is shortened by how long the node was awake, so that the reporting period does not drift.
+//!
+//! ## Alignment
+//! If [`WAKE_ALIGNMENT`] is set, wake-ups are aligned to wall-clock boundaries (e.g. every 10 minutes on the :00),
+//! so that the data from multiple nodes lines up. This requires the time to be synchronized over SNTP,
+//! which is done on every wake-up while connected.
+//!
+//! The RTC clock keeps the time during deep sleep, but it drifts by up to a few percent. The drift is learned
+//! from the correction made by every synchronization, averaged across cycles in RTC memory, and applied to
+//! the sleep time. Until the first synchronization, the sleep time is only compensated for the runtime.
+
+use super::{rtc::RtcCell, schedule::now_us, OsError, OsResult};
+use crate::{
+    config::{SNTP_TIMEOUT, WAKE_ALIGNMENT},
+    re_esp,
+};
+use esp_idf_svc::sntp::{EspSntp, SyncStatus};
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Shortest sleep time, wake-ups closer than this are moved to the next boundary.
+pub const MIN_SLEEP_TIME: Duration = Duration::from_secs(5);
+/// Interval of checking whether the time has been synchronized.
+const SYNC_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Minimum time between two synchronizations for learning the drift, shorter spans are too noisy.
+const MIN_DRIFT_SPAN: Duration = Duration::from_mins(1);
+/// Drift above this is not plausible, e.g. the first synchronization after a power loss.
+const MAX_DRIFT: f32 = 0.05;
+/// Weight of a new drift estimate in the moving average.
+const DRIFT_SMOOTHING: f32 = 0.3;
+/// Marks an empty value.
+const NO_VALUE: u32 = u32::MAX;
+
+/// Bits of the relative drift of the RTC clock (positive if it runs slow), kept in RTC memory.
+#[link_section = ".rtc.data"]
+static DRIFT: AtomicU32 = AtomicU32::new(NO_VALUE);
+
+/// System time after the last synchronization in microseconds, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static LAST_SYNC_US: RtcCell<Option<u64>> = RtcCell::new(None);
+
+/// Synchronize the time over SNTP and learn the drift of the RTC clock.
+///
+/// This should only be called while connected, and it blocks for up to [`SNTP_TIMEOUT`].
+///
+/// # Errors
+/// Returns an error if SNTP cannot be started, or if the time is not synchronized in time.
+pub fn sync() -> OsResult<()> {
+    let local_start_us = now_us();
+    let start = Instant::now();
+    let sntp = re_esp!(EspSntp::new_default(), SntpInit)?;
+
+    while sntp.get_sync_status() != SyncStatus::Completed {
+        if start.elapsed() >= SNTP_TIMEOUT {
+            return Err(OsError::SntpTimeout);
+        }
+
+        sleep(SYNC_POLL_INTERVAL);
+    }
+
+    // the system time has been stepped by the synchronization, unlike the monotonic clock
+    #[allow(clippy::cast_possible_truncation)]
+    let local_us = local_start_us + start.elapsed().as_micros() as u64;
+    let synced_us = now_us();
+    log::debug!(
+        "Time synchronized in {:.02?}, corrected by {}us",
+        start.elapsed(),
+        i128::from(synced_us) - i128::from(local_us)
+    );
+
+    let last_sync_us = LAST_SYNC_US.get();
+
+    if let Some(drift) = last_sync_us.and_then(|last| estimate_drift(last, local_us, synced_us)) {
+        let average = load_drift().map_or(drift, |previous| {
+            (drift - previous).mul_add(DRIFT_SMOOTHING, previous)
+        });
+        log::debug!("RTC clock drift: {:.0}ppm", average * 1_000_000.0);
+
+        DRIFT.store(average.to_bits(), Ordering::Relaxed);
+    }
+
+    LAST_SYNC_US.update(|last| *last = Some(synced_us));
+
+    Ok(())
+}
+
+/// Returns whether the time has been synchronized since the last power loss.
+pub fn is_synced() -> bool {
+    LAST_SYNC_US.get().is_some()
+}
+
+/// Returns how long to sleep, if the regular sleep time is `sleep_time` and the node has been awake for `elapsed`.
+pub fn sleep_time(sleep_time: Duration, elapsed: Duration) -> Duration {
+    let remaining = sleep_time.saturating_sub(elapsed).max(MIN_SLEEP_TIME);
+
+    let real = match WAKE_ALIGNMENT {
+        Some(alignment) if is_synced() => aligned_sleep_time(now_us(), remaining, alignment),
+        _ => remaining,
+    };
+
+    load_drift().map_or(real, |drift| correct_drift(real, drift))
+}
+
+/// Returns how long to sleep from `now_us`, to wake up at the alignment boundary closest to `remaining` from now.
+///
+/// Boundaries closer than [`MIN_SLEEP_TIME`] are skipped.
+#[allow(clippy::cast_possible_truncation)]
+pub const fn aligned_sleep_time(now_us: u64, remaining: Duration, alignment: Duration) -> Duration {
+    let alignment_us = alignment.as_micros() as u64;
+    if alignment_us == 0 {
+        return remaining;
+    }
+
+    let desired_us = now_us + remaining.as_micros() as u64;
+    let mut target_us = (desired_us + alignment_us / 2) / alignment_us * alignment_us;
+
+    while target_us < now_us + MIN_SLEEP_TIME.as_micros() as u64 {
+        target_us += alignment_us;
+    }
+
+    Duration::from_micros(target_us - now_us)
+}
+
+/// Estimate the relative drift of the RTC clock.
+///
+/// `last_sync_us` is the system time after the previous synchronization, `local_us` is the system time
+/// before the current one and `synced_us` after it. Returns `None` if the span is too short,
+/// or if the result is implausible.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+pub fn estimate_drift(last_sync_us: u64, local_us: u64, synced_us: u64) -> Option<f32> {
+    let span_us = local_us.checked_sub(last_sync_us)?;
+    if span_us < MIN_DRIFT_SPAN.as_micros() as u64 {
+        return None;
+    }
+
+    let drift = ((synced_us as f64 - local_us as f64) / span_us as f64) as f32;
+    (drift.abs() <= MAX_DRIFT).then_some(drift)
+}
+
+/// Convert a real duration into the duration measured by a clock with the given relative `drift`.
+pub fn correct_drift(duration: Duration, drift: f32) -> Duration {
+    duration.div_f32(1.0 + drift)
+}
+
+/// Load the drift estimate.
+fn load_drift() -> Option<f32> {
+    let bits = DRIFT.load(Ordering::Relaxed);
+    (bits != NO_VALUE).then(|| f32::from_bits(bits))
+}
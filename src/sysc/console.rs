@@ -0,0 +1,309 @@
+//! Maintenance console over the USB serial port.
+//!
+//! In maintenance mode (entered using the wake-up button), the node waits up to [`MAINTENANCE_TIMEOUT`]
+//! for a key press. If a key is pressed, the console is opened and the boot continues only after the `exit` command.
+//!
+//! If [`CONSOLE_TIMEOUT`] is set, the node also waits that long for a key press on every boot with USB connected.
+//! Since USB-powered nodes do not use deep sleep, the wake-up button cannot be used then.
+//!
+//! The battery calibration and resetting the statistics can only be done from here for now. Doing them from
+//! the server is blocked on a PWMP protocol change, since the server has no way to send commands to a node.
+//!
+//! ## Commands
+//! - `help`: List the available commands.
+//! - `cal show`: Show the battery calibration and the current readings.
+//! - `cal point <volts>`: Record a calibration point at the given real battery voltage.
+//!   The calibration is stored after the second point.
+//! - `cal reset`: Delete the battery calibration.
+//! - `stats show`: Show the reliability statistics.
+//! - `stats reset`: Clear the reliability statistics.
+//! - `exit`: Close the console and continue booting.
+
+use super::{
+    battery::{
+        calibration::{Calibration, CalibrationPoint},
+        Battery, BatteryChannel,
+    },
+    nvs::NonVolatileStorage,
+    stats, OsResult,
+};
+use crate::{
+    config::{CONSOLE_TIMEOUT, MAINTENANCE_TIMEOUT},
+    re_esp,
+};
+use esp_idf_svc::{
+    hal::delay::{TickType, BLOCK},
+    sys::{
+        esp, usb_serial_jtag_driver_config_t, usb_serial_jtag_driver_install,
+        usb_serial_jtag_read_bytes, usb_serial_jtag_write_bytes,
+    },
+};
+use std::{ffi::c_void, time::Duration};
+
+/// Size of the driver's buffers.
+const BUFFER_SIZE: u32 = 256;
+/// Maximum length of a command line.
+const MAX_LINE_LENGTH: usize = 64;
+/// Text shown by the `help` command.
+const HELP: &str = "Commands:
+  help               Show this help
+  cal show           Show the battery calibration
+  cal point <volts>  Record a calibration point at the given battery voltage
+  cal reset          Delete the battery calibration
+  stats show         Show the reliability statistics
+  stats reset        Clear the reliability statistics
+  exit               Continue booting";
+
+/// Handle for the USB serial console.
+pub struct Console(());
+
+impl Console {
+    /// Install the USB serial driver.
+    ///
+    /// # Errors
+    /// Returns an error if the driver cannot be installed.
+    pub fn open() -> OsResult<Self> {
+        let mut config = usb_serial_jtag_driver_config_t {
+            tx_buffer_size: BUFFER_SIZE,
+            rx_buffer_size: BUFFER_SIZE,
+        };
+
+        // SAFETY: The config is valid for the duration of the call.
+        re_esp!(
+            esp!(unsafe { usb_serial_jtag_driver_install(&raw mut config) }),
+            ConsoleInit
+        )?;
+
+        Ok(Self(()))
+    }
+
+    /// Wait up to `timeout` for a key press.
+    pub fn wait_for_key(&self, timeout: Duration) -> bool {
+        let ticks = TickType::new_millis(u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX));
+
+        self.read_byte(ticks.ticks()).is_some()
+    }
+
+    /// Block until a full line is entered and return it.
+    ///
+    /// Characters are echoed back and backspace is supported.
+    pub fn read_line(&self) -> String {
+        let mut line = String::new();
+
+        loop {
+            let Some(byte) = self.read_byte(BLOCK) else {
+                continue;
+            };
+
+            match byte {
+                b'\r' | b'\n' => {
+                    self.print("\r\n");
+                    return line;
+                }
+                0x08 | 0x7F if line.pop().is_some() => self.print("\x08 \x08"),
+                byte if (byte.is_ascii_graphic() || byte == b' ')
+                    && line.len() < MAX_LINE_LENGTH =>
+                {
+                    line.push(char::from(byte));
+                    self.write(&[byte]);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Print a line.
+    pub fn println(&self, text: &str) {
+        self.print(text);
+        self.print("\r\n");
+    }
+
+    /// Print text without a line break.
+    pub fn print(&self, text: &str) {
+        self.write(text.as_bytes());
+    }
+
+    /// Write raw bytes.
+    #[allow(clippy::unused_self)]
+    fn write(&self, data: &[u8]) {
+        // SAFETY: The buffer is valid for the duration of the call.
+        unsafe { usb_serial_jtag_write_bytes(data.as_ptr().cast::<c_void>(), data.len(), BLOCK) };
+    }
+
+    /// Read a single byte, waiting up to `ticks`.
+    #[allow(clippy::unused_self)]
+    fn read_byte(&self, ticks: u32) -> Option<u8> {
+        let mut byte = 0u8;
+
+        // SAFETY: The buffer is valid for the duration of the call.
+        let read =
+            unsafe { usb_serial_jtag_read_bytes((&raw mut byte).cast::<c_void>(), 1, ticks) };
+
+        (read > 0).then_some(byte)
+    }
+}
+
+/// Open the console, if [`CONSOLE_TIMEOUT`] is set and a key is pressed within it.
+///
+/// This blocks until the console is closed using the `exit` command.
+///
+/// # Errors
+/// Returns an error if the console cannot be opened.
+pub fn run_if_requested(
+    battery: &mut Battery<impl BatteryChannel>,
+    nvs: &NonVolatileStorage,
+) -> OsResult<()> {
+    let Some(timeout) = CONSOLE_TIMEOUT else {
+        return Ok(());
+    };
+
+    let console = Console::open()?;
+
+    log::info!("Press any key within {timeout:?} to open the console");
+    if !console.wait_for_key(timeout) {
+        return Ok(());
+    }
+
+    run(&console, battery, nvs);
+    Ok(())
+}
+
+/// Open the console in maintenance mode, if a key is pressed within [`MAINTENANCE_TIMEOUT`].
+///
+/// This blocks until the console is closed using the `exit` command.
+///
+/// # Errors
+/// Returns an error if the console cannot be opened.
+pub fn run_maintenance(
+    battery: &mut Battery<impl BatteryChannel>,
+    nvs: &NonVolatileStorage,
+) -> OsResult<()> {
+    let console = Console::open()?;
+
+    log::info!(
+        "Maintenance mode, press any key within {MAINTENANCE_TIMEOUT:?} to open the console"
+    );
+    if !console.wait_for_key(MAINTENANCE_TIMEOUT) {
+        log::warn!("Leaving maintenance mode, no key was pressed");
+        return Ok(());
+    }
+
+    run(&console, battery, nvs);
+    Ok(())
+}
+
+/// Run the console until the `exit` command.
+fn run(console: &Console, battery: &mut Battery<impl BatteryChannel>, nvs: &NonVolatileStorage) {
+    console.println("PixelWeatherOS console, type `help` for a list of commands");
+
+    loop {
+        console.print("> ");
+        let line = console.read_line();
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match args.as_slice() {
+            [] => (),
+            ["help"] => console.println(HELP),
+            ["cal", args @ ..] => {
+                if let Err(why) = calibration_command(console, args, battery, nvs) {
+                    console.println(&format!("Error: {why}"));
+                }
+            }
+            ["stats", args @ ..] => stats_command(console, args, nvs),
+            ["exit"] => break,
+            _ => console.println("Unknown command, type `help` for a list of commands"),
+        }
+    }
+
+    console.println("Continuing boot");
+}
+
+/// Handle the `cal` commands.
+fn calibration_command(
+    console: &Console,
+    args: &[&str],
+    battery: &mut Battery<impl BatteryChannel>,
+    nvs: &NonVolatileStorage,
+) -> OsResult<()> {
+    match args {
+        ["show"] => {
+            match nvs.get_battery_calibration()? {
+                Some(calibration) => console.println(&format!(
+                    "Calibration: gain {:.05}, offset {:+.04}V",
+                    calibration.gain, calibration.offset
+                )),
+                None => console.println("Not calibrated"),
+            }
+
+            if let Some(point) = nvs.get_battery_calibration_point()? {
+                console.println(&format!(
+                    "Pending point: measured {:.03}V, actual {:.03}V",
+                    point.measured, point.actual
+                ));
+            }
+
+            console.println(&format!(
+                "Measured: {:.03}V, calibrated: {:.03}V",
+                battery.read_uncalibrated()?,
+                battery.read()?
+            ));
+        }
+        ["point", volts] => {
+            let Ok(actual) = volts.parse::<f32>() else {
+                console.println("Invalid voltage");
+                return Ok(());
+            };
+
+            let point = CalibrationPoint {
+                measured: battery.read_uncalibrated()?,
+                actual,
+            };
+
+            let Some(first) = nvs.get_battery_calibration_point()? else {
+                nvs.store_battery_calibration_point(point)?;
+                console.println(&format!(
+                    "Recorded first point (measured {:.03}V), now change the voltage and record the second one",
+                    point.measured
+                ));
+                return Ok(());
+            };
+
+            let Some(calibration) = Calibration::from_points(first, point) else {
+                console.println("The points are too close together or inconsistent, try again");
+                return Ok(());
+            };
+
+            nvs.store_battery_calibration(calibration)?;
+            nvs.clear_battery_calibration_point()?;
+            battery.set_calibration(Some(calibration));
+
+            console.println(&format!(
+                "Calibration stored: gain {:.05}, offset {:+.04}V",
+                calibration.gain, calibration.offset
+            ));
+        }
+        ["reset"] => {
+            nvs.clear_battery_calibration()?;
+            battery.set_calibration(None);
+            console.println("Calibration deleted");
+        }
+        _ => console.println("Usage: cal show | cal point <volts> | cal reset"),
+    }
+
+    Ok(())
+}
+
+/// Handle the `stats` commands.
+fn stats_command(console: &Console, args: &[&str], nvs: &NonVolatileStorage) {
+    match args {
+        ["show"] => match stats::current() {
+            Some(stats) => console.println(&stats.summary()),
+            None => console.println("No statistics loaded"),
+        },
+        ["reset"] => {
+            stats::reset(nvs);
+            console.println("Statistics cleared");
+        }
+        _ => console.println("Usage: stats show | stats reset"),
+    }
+}
@@ -96,6 +96,34 @@ pub enum OsError {
     #[error("Failed to read ADC ({0})")]
     AdcRead(EspError),
 
+    /// Failed to set up the USB serial console.
+    #[error("Failed to initialize console ({0})")]
+    ConsoleInit(EspError),
+
+    /// Failed to start SNTP.
+    #[error("Failed to start SNTP ({0})")]
+    SntpInit(EspError),
+
+    /// The time was not synchronized within the timeout.
+    #[error("Time synchronization timed out")]
+    SntpTimeout,
+
+    /// Failed to configure power management.
+    #[error("Failed to configure power management ({0})")]
+    PmInit(EspError),
+
+    /// Failed to start a timer.
+    #[error("Failed to start timer ({0})")]
+    TimerInit(EspError),
+
+    /// A phase of the wake cycle has exceeded its time budget.
+    #[error("Phase `{0}` has exceeded its time budget")]
+    PhaseTimeout(&'static str),
+
+    /// The wake cycle has exceeded its deadline.
+    #[error("Wake cycle has exceeded its deadline")]
+    RunTimeout,
+
     /// Error while performing a write-read operation on I2C.
     #[error("Failed to perform W/R on I2C ({esp_err}): Write {data:?} to addr {addr}")]
     I2cWr {
@@ -112,6 +140,35 @@ pub enum OsError {
         esp_err: EspError,
     },
 
+    /// Data received from an I2C device has an invalid CRC.
+    #[error("I2C CRC mismatch from addr {0}")]
+    I2cCrc(u8),
+
+    /// A sensor has no measurement available.
+    #[cfg(feature = "scd4x")]
+    #[error("Sensor measurement is not ready")]
+    SensorNotReady,
+
+    /// Error while reading from or writing to UART.
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    #[error("UART I/O failed ({0})")]
+    UartIo(EspError),
+
+    /// Failed to initialize UART.
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    #[error("Failed to initialize UART ({0})")]
+    UartInit(EspError),
+
+    /// A sensor has sent an incomplete or corrupted frame.
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    #[error("Invalid frame received from sensor")]
+    SensorFrame,
+
+    /// Error on the 1-Wire bus.
+    #[cfg(feature = "ds18b20")]
+    #[error("{0}")]
+    OneWire(#[from] super::ext_drivers::onewire::Error),
+
     /// Specified parameter was too long.
     #[error("Argument too long")]
     ArgumentTooLong,
@@ -120,6 +177,10 @@ pub enum OsError {
     #[error("Unexpected version format")]
     IllegalFirmwareVersion,
 
+    /// The brownout threshold level is out of range.
+    #[error("Illegal brownout threshold level ({0})")]
+    IllegalBrownoutLevel(u8),
+
     /// Partition metadata is missing.
     #[error("Unexpected version format")]
     MissingPartitionMetadata,
@@ -145,6 +206,28 @@ pub enum OsError {
     InternalTempSensorRead(EspError),
 }
 
+/// Categories of [`OsError`]s, used for statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// `WiFi` and Internet connectivity
+    Network,
+
+    /// Communication with the PWMP server
+    Server,
+
+    /// Sensors and their buses
+    Sensor,
+
+    /// NVS and OTA storage
+    Storage,
+
+    /// A phase or the whole wake cycle took too long
+    Timeout,
+
+    /// Everything else
+    System,
+}
+
 /// Trait for non-fatal error types that can be "reported" to the console.
 ///
 /// This trait is meant to be implemented for [`Result`]s.
@@ -153,6 +236,35 @@ pub trait ReportableError {
     fn report(self, desc: &str);
 }
 
+impl ErrorCategory {
+    /// All categories, in order.
+    pub const ALL: [Self; 6] = [
+        Self::Network,
+        Self::Server,
+        Self::Sensor,
+        Self::Storage,
+        Self::Timeout,
+        Self::System,
+    ];
+
+    /// Short name used in reports.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Network => "network",
+            Self::Server => "server",
+            Self::Sensor => "sensor",
+            Self::Storage => "storage",
+            Self::Timeout => "timeout",
+            Self::System => "system",
+        }
+    }
+
+    /// Index of the category in [`ErrorCategory::ALL`].
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
 impl<T, E: Display> ReportableError for Result<T, E> {
     fn report(self, desc: &str) {
         if let Err(why) = self {
@@ -172,6 +284,50 @@ impl OsError {
         )
     }
 
+    /// Returns the category of the error.
+    pub const fn category(&self) -> ErrorCategory {
+        match self {
+            Self::WifiInit(..)
+            | Self::WifiConnect(..)
+            | Self::WifiParam(..)
+            | Self::WifiConfig(..)
+            | Self::WifiStart(..)
+            | Self::WifiScan(..)
+            | Self::WifiInfo(..)
+            | Self::EventTimeout(..)
+            | Self::EventWaiterInit(..)
+            | Self::NoInternet
+            | Self::SntpInit(..)
+            | Self::SntpTimeout => ErrorCategory::Network,
+            Self::PwmpError(..) => ErrorCategory::Server,
+            Self::NoEnvSensor
+            | Self::AdcInit(..)
+            | Self::AdcRead(..)
+            | Self::I2cWr { .. }
+            | Self::I2cWrite { .. }
+            | Self::I2cCrc(..)
+            | Self::InternalTempSensorRead(..) => ErrorCategory::Sensor,
+            #[cfg(feature = "scd4x")]
+            Self::SensorNotReady => ErrorCategory::Sensor,
+            #[cfg(any(feature = "pms5003", feature = "sds011"))]
+            Self::UartIo(..) | Self::UartInit(..) | Self::SensorFrame => ErrorCategory::Sensor,
+            #[cfg(feature = "ds18b20")]
+            Self::OneWire(..) => ErrorCategory::Sensor,
+            Self::OtaInit(..)
+            | Self::OtaWrite(..)
+            | Self::OtaAbort(..)
+            | Self::OtaSlot(..)
+            | Self::NvsInit(..)
+            | Self::NvsRead(..)
+            | Self::NvsWrite(..)
+            | Self::InvalidNvsKey
+            | Self::IllegalFirmwareVersion
+            | Self::MissingPartitionMetadata => ErrorCategory::Storage,
+            Self::PhaseTimeout(..) | Self::RunTimeout => ErrorCategory::Timeout,
+            _ => ErrorCategory::System,
+        }
+    }
+
     pub fn from_i2c_writeop(
         result: Result<(), EspError>,
         addr: u8,
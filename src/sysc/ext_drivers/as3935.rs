@@ -0,0 +1,219 @@
+//! Driver for the ams AS3935 Franklin lightning sensor.
+//!
+//! The sensor listens for lightning on its own and signals events on its IRQ pin, which stays
+//! high until the interrupt register is read. This allows the MCU to sleep in the meantime.
+//!
+//! These sensors work over the I2C protocol.
+
+use crate::sysc::{OsError, OsResult};
+use esp_idf_svc::hal::i2c::I2cDriver;
+use std::{thread::sleep, time::Duration};
+
+/// Registers of AS3935 sensors.
+#[derive(Clone, Copy)]
+enum Register {
+    /// AFE gain and power-down
+    AfeGain = 0x00,
+
+    /// Noise floor level and watchdog threshold
+    NoiseFloor = 0x01,
+
+    /// Disturber mask and interrupt flags
+    Interrupt = 0x03,
+
+    /// Strike energy, least significant byte
+    EnergyLsb = 0x04,
+
+    /// Estimated distance of the storm front
+    Distance = 0x07,
+
+    /// Oscillator display and tuning capacitors
+    Tuning = 0x08,
+
+    /// Writing [`DIRECT_COMMAND`] restores the default settings
+    PresetDefault = 0x3C,
+
+    /// Writing [`DIRECT_COMMAND`] calibrates the internal RC oscillators
+    CalibrateRco = 0x3D,
+}
+
+/// Value that triggers a direct command.
+const DIRECT_COMMAND: u8 = 0x96;
+/// Distance value reported for storms out of range.
+const OUT_OF_RANGE: u8 = 0x3F;
+/// Highest noise floor level.
+const MAX_NOISE_FLOOR: u8 = 7;
+
+/// Events signalled by the sensor.
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    /// No event is pending.
+    None,
+
+    /// The noise level is above the noise floor, so lightning cannot be detected.
+    NoiseTooHigh,
+
+    /// A man-made disturber was detected.
+    Disturber,
+
+    /// Lightning was detected.
+    Lightning {
+        /// Estimated distance of the storm front in km, `None` if it's out of range (>40km).
+        distance: Option<u8>,
+
+        /// Energy of the strike, this has no physical meaning.
+        energy: u32,
+    },
+}
+
+/// Driver handle for AS3935 sensors.
+pub struct As3935<'s> {
+    /// I2C driver handle for communication with the sensor.
+    i2c: &'s mut I2cDriver<'static>,
+}
+
+impl<'s> As3935<'s> {
+    /// Known default address
+    pub const DEV_ADDR: u8 = 0x03;
+
+    const BUS_TIMEOUT: u32 = 1000;
+
+    /// Initialize the driver with the given I2C driver handle.
+    ///
+    /// The sensor keeps its settings as long as it's powered, so no setup is done here.
+    pub fn new_with_driver(driver: &'s mut I2cDriver<'static>) -> Self {
+        log::debug!("Loading driver");
+        Self { i2c: driver }
+    }
+
+    /// Restore the default settings and calibrate the internal oscillators.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn reset(&mut self) -> OsResult<()> {
+        self.write(Register::PresetDefault, DIRECT_COMMAND)?;
+        sleep(Duration::from_millis(2));
+
+        self.write(Register::CalibrateRco, DIRECT_COMMAND)?;
+
+        // the calibration is finished by displaying the oscillator on the IRQ pin for 2ms
+        self.modify(Register::Tuning, 0b0010_0000, 0b0010_0000)?;
+        sleep(Duration::from_millis(2));
+        self.modify(Register::Tuning, 0b0010_0000, 0)
+    }
+
+    /// Configure the detection parameters.
+    ///
+    /// - `indoor` selects the AFE gain, indoor operation is more sensitive.
+    /// - `noise_floor` sets the noise floor level (`0..=7`), higher levels tolerate more noise.
+    /// - `mask_disturbers` stops disturbers from triggering an interrupt.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn configure(
+        &mut self,
+        indoor: bool,
+        noise_floor: u8,
+        mask_disturbers: bool,
+    ) -> OsResult<()> {
+        let gain = if indoor { 0b10010 } else { 0b01110 };
+
+        self.modify(Register::AfeGain, 0b0011_1111, gain << 1)?;
+        self.modify(
+            Register::NoiseFloor,
+            0b0111_0000,
+            noise_floor.min(MAX_NOISE_FLOOR) << 4,
+        )?;
+        self.modify(
+            Register::Interrupt,
+            0b0010_0000,
+            u8::from(mask_disturbers) << 5,
+        )
+    }
+
+    /// Read and clear the pending event.
+    ///
+    /// The event can only be read 2ms after the IRQ pin went high.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn read_event(&mut self) -> OsResult<Event> {
+        sleep(Duration::from_millis(2));
+
+        match self.read(Register::Interrupt)? & 0x0F {
+            0x00 => Ok(Event::None),
+            0x01 => Ok(Event::NoiseTooHigh),
+            0x04 => Ok(Event::Disturber),
+            0x08 => {
+                let mut energy = [0u8; 3];
+                self.read_into(Register::EnergyLsb, &mut energy)?;
+                let distance = self.read(Register::Distance)? & 0x3F;
+
+                Ok(Event::Lightning {
+                    distance: (distance != OUT_OF_RANGE).then_some(distance),
+                    energy: u32::from_le_bytes([energy[0], energy[1], energy[2] & 0x1F, 0]),
+                })
+            }
+            other => {
+                log::warn!("Unknown interrupt 0x{other:X}");
+                Ok(Event::None)
+            }
+        }
+    }
+
+    /// Increase the noise floor level by one step.
+    ///
+    /// Returns the new level, or `None` if it's already at the maximum.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn raise_noise_floor(&mut self) -> OsResult<Option<u8>> {
+        let level = (self.read(Register::NoiseFloor)? >> 4) & 0b111;
+
+        if level >= MAX_NOISE_FLOOR {
+            return Ok(None);
+        }
+
+        self.modify(Register::NoiseFloor, 0b0111_0000, (level + 1) << 4)?;
+        Ok(Some(level + 1))
+    }
+
+    /// Set the bits selected by `mask` in a register to `value`, keeping the other bits.
+    fn modify(&mut self, register: Register, mask: u8, value: u8) -> OsResult<()> {
+        let current = self.read(register)?;
+        self.write(register, (current & !mask) | (value & mask))
+    }
+
+    /// Write a single register.
+    fn write(&mut self, register: Register, value: u8) -> OsResult<()> {
+        let data = [register as u8, value];
+
+        OsError::from_i2c_writeop(
+            self.i2c.write(Self::DEV_ADDR, &data, Self::BUS_TIMEOUT),
+            Self::DEV_ADDR,
+            &data,
+            false,
+        )
+    }
+
+    /// Read a single register.
+    fn read(&mut self, register: Register) -> OsResult<u8> {
+        let mut buffer = [0u8; 1];
+        self.read_into(register, &mut buffer)?;
+
+        Ok(buffer[0])
+    }
+
+    /// Read consecutive registers into the buffer, starting at `register`.
+    fn read_into(&mut self, register: Register, buffer: &mut [u8]) -> OsResult<()> {
+        let data = [register as u8];
+
+        OsError::from_i2c_writeop(
+            self.i2c
+                .write_read(Self::DEV_ADDR, &data, buffer, Self::BUS_TIMEOUT),
+            Self::DEV_ADDR,
+            &data,
+            true,
+        )
+    }
+}
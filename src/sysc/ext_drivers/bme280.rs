@@ -10,14 +10,14 @@
 //! These sensors work over the I2C protocol.
 
 use super::EnvironmentSensor;
-use crate::sysc::{OsError, OsResult, ReportableError};
+use crate::sysc::{pm, OsError, OsResult, ReportableError};
 use esp_idf_svc::hal::i2c::I2cDriver;
 use pwmp_client::pwmp_msg::aliases::{AirPressure, Humidity, Temperature};
 
 /// Driver handle for Bosch BME280 sensors.
 pub struct BoschME280<'s> {
     /// I2C driver handle for communication with the sensor.
-    i2c: I2cDriver<'s>,
+    i2c: &'s mut I2cDriver<'static>,
 
     /// I2C address of the sensor.
     addr: u8,
@@ -89,7 +89,7 @@ impl<'s> BoschME280<'s> {
     const BUS_TIMEOUT: u32 = 1000;
 
     /// Initialize the driver with the given I2C driver handle.
-    pub fn new_with_driver(driver: I2cDriver<'s>, addr: u8) -> Result<Self, OsError> {
+    pub fn new_with_driver(driver: &'s mut I2cDriver<'static>, addr: u8) -> Result<Self, OsError> {
         log::debug!("Loading driver");
         let mut dev = Self {
             i2c: driver,
@@ -118,7 +118,7 @@ impl<'s> BoschME280<'s> {
 
         // quirk: due to the highest quality and sampling settings, measurement takes
         //        about 112ms, so we block the caller before it can proceed to read the sensor
-        std::thread::sleep(std::time::Duration::from_millis(120));
+        pm::idle(|| std::thread::sleep(std::time::Duration::from_millis(120)));
 
         Ok(dev)
     }
@@ -8,7 +8,7 @@
 //! These sensors work over the I2C protocol.
 
 use super::EnvironmentSensor;
-use crate::sysc::{OsError, OsResult};
+use crate::sysc::{pm, OsError, OsResult};
 use esp_idf_svc::hal::i2c::I2cDriver;
 use pwmp_client::pwmp_msg::aliases::{AirPressure, Humidity, Temperature};
 use std::{thread::sleep, time::Duration};
@@ -30,7 +30,7 @@ enum Command {
 }
 
 /// Driver handle for HTU21D (and similar) sensors.
-pub struct Htu<'s>(I2cDriver<'s>);
+pub struct Htu<'s>(&'s mut I2cDriver<'static>);
 
 impl<'s> Htu<'s> {
     /// Known default address
@@ -40,7 +40,7 @@ impl<'s> Htu<'s> {
     const CMD_WAIT_TIME: u64 = 50;
 
     /// Initialize the driver with the given I2C driver handle.
-    pub fn new_with_driver(driver: I2cDriver<'s>) -> Result<Self, OsError> {
+    pub fn new_with_driver(driver: &'s mut I2cDriver<'static>) -> Result<Self, OsError> {
         log::debug!("Loading driver");
         let mut dev = Self(driver);
 
@@ -71,7 +71,7 @@ impl<'s> Htu<'s> {
 
     fn reset(&mut self) -> OsResult<()> {
         self.write(Command::Reset)?;
-        sleep(Duration::from_millis(Self::CMD_WAIT_TIME));
+        pm::idle(|| sleep(Duration::from_millis(Self::CMD_WAIT_TIME)));
         Ok(())
     }
 
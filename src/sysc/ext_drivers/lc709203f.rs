@@ -0,0 +1,180 @@
+//! Driver for the onsemi LC709203F fuel gauge.
+//!
+//! The fuel gauge tracks the state of charge on its own, but it must be told the capacity of the battery.
+//! This is done using an adjustment value (APA), which is selected from the datasheet's table for the given capacity.
+//! The cell temperature is not measured, so the default of 25*C is used for compensation.
+//!
+//! Unlike the MAX17048, this fuel gauge does not report the charge rate.
+//!
+//! These fuel gauges work over the I2C protocol. Every transfer is protected by a CRC-8 checksum.
+
+use crate::sysc::{battery::source::BatterySource, OsError, OsResult};
+use esp_idf_svc::hal::i2c::I2cDriver;
+
+/// Registers of LC709203F fuel gauges.
+#[derive(Clone, Copy)]
+enum Register {
+    /// Cell voltage (mV)
+    CellVoltage = 0x09,
+
+    /// Adjustment pack application, selects the battery capacity
+    Apa = 0x0B,
+
+    /// Indicator to empty (0.1%)
+    Ite = 0x0F,
+
+    /// IC version
+    IcVersion = 0x11,
+
+    /// Power mode, `1` for operational and `2` for sleep
+    PowerMode = 0x15,
+
+    /// Temperature source, `0` for I2C and `1` for a thermistor
+    StatusBit = 0x16,
+}
+
+/// Operational power mode.
+const POWER_MODE_OPERATIONAL: u16 = 0x0001;
+/// Temperature is set over I2C, the default is 25*C.
+const TEMPERATURE_MODE_I2C: u16 = 0x0000;
+/// APA values for a 3.7V Li-ion battery by capacity (mAh), as given in the datasheet.
+const APA_TABLE: &[(u16, u16)] = &[
+    (100, 0x08),
+    (200, 0x0B),
+    (500, 0x10),
+    (1000, 0x19),
+    (2000, 0x2D),
+    (3000, 0x36),
+];
+
+/// Driver handle for LC709203F fuel gauges.
+pub struct Lc709203f<'s> {
+    /// I2C driver handle for communication with the fuel gauge.
+    i2c: &'s mut I2cDriver<'static>,
+}
+
+impl<'s> Lc709203f<'s> {
+    /// Known default address
+    pub const DEV_ADDR: u8 = 0x0B;
+
+    const BUS_TIMEOUT: u32 = 1000;
+
+    /// Initialize the driver with the given I2C driver handle and configure it for a battery
+    /// with the given `capacity` in mAh.
+    ///
+    /// The settings are kept by the fuel gauge as long as it's powered, so they are only written if they differ.
+    ///
+    /// # Errors
+    /// Returns an error if the fuel gauge does not respond.
+    pub fn new_with_driver(driver: &'s mut I2cDriver<'static>, capacity: u16) -> OsResult<Self> {
+        log::debug!("Loading driver");
+        let mut dev = Self { i2c: driver };
+
+        let version = dev.read(Register::IcVersion)?;
+        log::debug!("IC version: 0x{version:04X}");
+
+        dev.write_if_changed(Register::PowerMode, POWER_MODE_OPERATIONAL)?;
+        dev.write_if_changed(Register::StatusBit, TEMPERATURE_MODE_I2C)?;
+        dev.write_if_changed(Register::Apa, apa_for_capacity(capacity))?;
+
+        Ok(dev)
+    }
+
+    /// Write a register, if its value differs from `value`.
+    fn write_if_changed(&mut self, register: Register, value: u16) -> OsResult<()> {
+        if self.read(register)? == value {
+            return Ok(());
+        }
+
+        log::debug!("Setting register 0x{:02X} to 0x{value:04X}", register as u8);
+        self.write(register, value)
+    }
+
+    /// Write a 16-bit register.
+    fn write(&mut self, register: Register, value: u16) -> OsResult<()> {
+        let [lo, hi] = value.to_le_bytes();
+        let crc = crc8(&[Self::DEV_ADDR << 1, register as u8, lo, hi]);
+        let data = [register as u8, lo, hi, crc];
+
+        OsError::from_i2c_writeop(
+            self.i2c.write(Self::DEV_ADDR, &data, Self::BUS_TIMEOUT),
+            Self::DEV_ADDR,
+            &data,
+            false,
+        )
+    }
+
+    /// Read a 16-bit register, while checking its CRC.
+    fn read(&mut self, register: Register) -> OsResult<u16> {
+        let data = [register as u8];
+        let mut buffer = [0u8; 3];
+
+        OsError::from_i2c_writeop(
+            self.i2c
+                .write_read(Self::DEV_ADDR, &data, &mut buffer, Self::BUS_TIMEOUT),
+            Self::DEV_ADDR,
+            &data,
+            true,
+        )?;
+
+        // the checksum covers the addresses and the register as well
+        let crc = crc8(&[
+            Self::DEV_ADDR << 1,
+            register as u8,
+            (Self::DEV_ADDR << 1) | 1,
+            buffer[0],
+            buffer[1],
+        ]);
+        if crc != buffer[2] {
+            return Err(OsError::I2cCrc(Self::DEV_ADDR));
+        }
+
+        Ok(u16::from_le_bytes([buffer[0], buffer[1]]))
+    }
+}
+
+impl BatterySource for Lc709203f<'_> {
+    fn read_voltage(&mut self) -> OsResult<f32> {
+        Ok(f32::from(self.read(Register::CellVoltage)?) / 1000.0)
+    }
+
+    fn read_soc(&mut self) -> OsResult<Option<f32>> {
+        Ok(Some(f32::from(self.read(Register::Ite)?) / 10.0))
+    }
+
+    fn read_charge_rate(&mut self) -> OsResult<Option<f32>> {
+        Ok(None)
+    }
+}
+
+/// Select the APA value for the closest capacity in [`APA_TABLE`].
+fn apa_for_capacity(capacity: u16) -> u16 {
+    APA_TABLE
+        .iter()
+        .min_by_key(|(table_capacity, _)| table_capacity.abs_diff(capacity))
+        .map_or(APA_TABLE[0].1, |(_, apa)| *apa)
+}
+
+/// Calculate the CRC-8 checksum used by the fuel gauge (polynomial `0x07`, initial value `0x00`).
+const fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0x00;
+    let mut i = 0;
+
+    while i < data.len() {
+        crc ^= data[i];
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+
+        i += 1;
+    }
+
+    crc
+}
@@ -0,0 +1,94 @@
+//! Driver for the Analog Devices MAX17048 fuel gauge.
+//!
+//! The fuel gauge tracks the state of charge on its own using the `ModelGauge` algorithm, so no
+//! configuration is needed. It measures the cell voltage directly, which is far more accurate than
+//! the resistor divider used by the ADC measurement.
+//!
+//! These fuel gauges work over the I2C protocol.
+
+use crate::sysc::{battery::source::BatterySource, OsError, OsResult};
+use esp_idf_svc::hal::i2c::I2cDriver;
+
+/// Registers of MAX17048 fuel gauges.
+#[derive(Clone, Copy)]
+enum Register {
+    /// Cell voltage
+    Vcell = 0x02,
+
+    /// State of charge
+    Soc = 0x04,
+
+    /// Production version
+    Version = 0x08,
+
+    /// Charge or discharge rate
+    Crate = 0x16,
+}
+
+/// Driver handle for MAX17048 fuel gauges.
+pub struct Max17048<'s> {
+    /// I2C driver handle for communication with the fuel gauge.
+    i2c: &'s mut I2cDriver<'static>,
+}
+
+impl<'s> Max17048<'s> {
+    /// Known default address
+    pub const DEV_ADDR: u8 = 0x36;
+
+    const BUS_TIMEOUT: u32 = 1000;
+    /// Cell voltage per LSB of [`Register::Vcell`].
+    const VCELL_LSB: f32 = 78.125e-6;
+    /// Percent per hour per LSB of [`Register::Crate`].
+    const CRATE_LSB: f32 = 0.208;
+
+    /// Initialize the driver with the given I2C driver handle.
+    ///
+    /// # Errors
+    /// Returns an error if the fuel gauge does not respond.
+    pub fn new_with_driver(driver: &'s mut I2cDriver<'static>) -> OsResult<Self> {
+        log::debug!("Loading driver");
+        let mut dev = Self { i2c: driver };
+
+        let version = dev.read(Register::Version)?;
+        log::debug!("Version: 0x{version:04X}");
+
+        Ok(dev)
+    }
+
+    /// Read a 16-bit register.
+    fn read(&mut self, register: Register) -> OsResult<u16> {
+        let data = [register as u8];
+        let mut buffer = [0u8; 2];
+
+        OsError::from_i2c_writeop(
+            self.i2c
+                .write_read(Self::DEV_ADDR, &data, &mut buffer, Self::BUS_TIMEOUT),
+            Self::DEV_ADDR,
+            &data,
+            true,
+        )?;
+
+        Ok(u16::from_be_bytes(buffer))
+    }
+}
+
+impl BatterySource for Max17048<'_> {
+    fn read_voltage(&mut self) -> OsResult<f32> {
+        Ok(f32::from(self.read(Register::Vcell)?) * Self::VCELL_LSB)
+    }
+
+    fn read_soc(&mut self) -> OsResult<Option<f32>> {
+        // the upper byte is in 1%, the lower one in 1/256%
+        let raw = self.read(Register::Soc)?;
+
+        // the model may report slightly more than 100% on a full battery
+        Ok(Some((f32::from(raw) / 256.0).clamp(0.0, 100.0)))
+    }
+
+    fn read_charge_rate(&mut self) -> OsResult<Option<f32>> {
+        #[allow(clippy::cast_possible_wrap)]
+        let raw = self.read(Register::Crate)? as i16;
+
+        Ok(Some(f32::from(raw) * Self::CRATE_LSB))
+    }
+}
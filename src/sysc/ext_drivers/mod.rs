@@ -1,13 +1,44 @@
+#[cfg(feature = "as3935")]
+mod as3935;
 mod bme280;
 mod envsensor_trait;
 mod htu;
+mod lc709203f;
+mod max17048;
+#[cfg(feature = "ds18b20")]
+pub mod onewire;
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+mod particulate;
+#[cfg(feature = "pms5003")]
+mod pms5003;
+#[cfg(feature = "scd4x")]
+mod scd4x;
+#[cfg(feature = "sds011")]
+mod sds011;
 
 use super::OsResult;
+#[cfg(feature = "as3935")]
+pub use as3935::{As3935, Event as LightningEvent};
 pub use bme280::BoschME280;
+#[cfg(feature = "ds18b20")]
+pub use pwos_logic::ds18b20::{Ds18b20, Resolution as Ds18b20Resolution};
 pub use envsensor_trait::EnvironmentSensor;
 pub use htu::Htu;
+pub use lc709203f::Lc709203f;
+pub use max17048::Max17048;
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+pub use particulate::ParticulateSensor;
+#[cfg(feature = "pms5003")]
+pub use pms5003::Pms5003;
 use pwmp_client::pwmp_msg::aliases::{AirPressure, Humidity, Temperature};
 
+#[cfg(all(feature = "pms5003", feature = "sds011"))]
+compile_error!("Only one particulate matter sensor can be enabled at a time");
+#[cfg(feature = "scd4x")]
+pub use scd4x::Scd4x;
+#[cfg(feature = "sds011")]
+pub use sds011::Sds011;
+
 /// A wrapper that allows abstracting the underlying sensor driver without the use of generics.
 pub enum AnySensor<'s> {
     HtuCompatible(Htu<'s>),
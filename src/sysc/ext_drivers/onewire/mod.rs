@@ -0,0 +1,9 @@
+//! Bit-banged 1-Wire bus driver.
+//!
+//! The protocol layer is in [`pwos_logic::onewire`], which does not depend on the hardware.
+//! [`PinOneWire`] implements the bus on a single open-drain GPIO pin.
+
+mod pin;
+
+pub use pin::PinOneWire;
+pub use pwos_logic::onewire::Error;
@@ -0,0 +1,103 @@
+//! 1-Wire bus master on a single GPIO pin.
+
+use crate::{re_esp, sysc::OsResult};
+use esp_idf_svc::{
+    hal::{
+        delay::Ets,
+        gpio::{AnyIOPin, InputOutput, PinDriver, Pull},
+        interrupt,
+    },
+    sys::{
+        gpio_mode_t_GPIO_MODE_INPUT_OUTPUT, gpio_mode_t_GPIO_MODE_INPUT_OUTPUT_OD,
+        gpio_set_direction,
+    },
+};
+use pwos_logic::onewire::OneWireBus;
+
+/// 1-Wire bus master on a single GPIO pin.
+///
+/// The pin is used in open-drain mode and relies on an external pull-up resistor (typically 4.7kOhm).
+/// The internal pull-up is enabled as well, but it is too weak to be used on its own.
+pub struct PinOneWire(PinDriver<'static, InputOutput>);
+
+impl PinOneWire {
+    /// Initialize the bus on the given pin.
+    ///
+    /// # Errors
+    /// Returns an error if [`PinDriver::input_output_od`] fails.
+    pub fn new(pin: AnyIOPin<'static>) -> OsResult<Self> {
+        let mut driver = re_esp!(PinDriver::input_output_od(pin), GpioInit)?;
+        re_esp!(driver.set_pull(Pull::Up), GpioInit)?;
+        re_esp!(driver.set_high(), GpioInit)?;
+
+        Ok(Self(driver))
+    }
+
+    /// Release the bus and let the pull-up resistor pull it high.
+    fn release(&mut self) {
+        let _ = self.0.set_high();
+    }
+
+    /// Pull the bus low.
+    fn pull_low(&mut self) {
+        let _ = self.0.set_low();
+    }
+}
+
+impl OneWireBus for PinOneWire {
+    fn reset(&mut self) -> bool {
+        // The timing of the whole sequence is critical, so it must not be interrupted.
+        interrupt::free(|| {
+            self.pull_low();
+            Ets::delay_us(480);
+            self.release();
+            Ets::delay_us(70);
+            let presence = self.0.is_low();
+            Ets::delay_us(410);
+
+            presence
+        })
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        interrupt::free(|| {
+            self.pull_low();
+
+            if bit {
+                Ets::delay_us(6);
+                self.release();
+                Ets::delay_us(64);
+            } else {
+                Ets::delay_us(60);
+                self.release();
+                Ets::delay_us(10);
+            }
+        });
+    }
+
+    fn read_bit(&mut self) -> bool {
+        interrupt::free(|| {
+            self.pull_low();
+            Ets::delay_us(6);
+            self.release();
+            Ets::delay_us(9);
+            let bit = self.0.is_high();
+            Ets::delay_us(55);
+
+            bit
+        })
+    }
+
+    fn set_strong_pullup(&mut self, enable: bool) {
+        let mode = if enable {
+            gpio_mode_t_GPIO_MODE_INPUT_OUTPUT
+        } else {
+            gpio_mode_t_GPIO_MODE_INPUT_OUTPUT_OD
+        };
+
+        self.release();
+
+        // SAFETY: The pin is owned by this driver, only its output stage is changed.
+        unsafe { gpio_set_direction(self.0.pin(), mode) };
+    }
+}
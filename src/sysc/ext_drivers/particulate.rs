@@ -0,0 +1,50 @@
+//! Common interface of particulate matter sensors.
+//!
+//! ## How it works
+//! - The supported sensors ([PMS5003](super::pms5003) and [SDS011](super::sds011)) draw air in using a fan
+//!   and count the particles with a laser. Only one of them can be enabled at a time.
+//! - The fan must run for [`WARMUP_TIME`](ParticulateSensor::WARMUP_TIME) before the readings are reliable,
+//!   so the sensor is started early in the wake cycle, and read once the warm-up is over.
+//! - The sensors are switched into a polling mode, where they only send a measurement frame when requested.
+//!   The frames are checksummed, and invalid ones are rejected.
+//! - The sensor is put to sleep (stopping the fan) when the driver is dropped.
+//!
+//! These sensors work over the UART protocol (9600 baud).
+
+use crate::sysc::OsResult;
+use esp_idf_svc::hal::uart::UartDriver;
+use std::time::Duration;
+
+/// Particulate matter concentrations in µg/m³.
+pub struct ParticulateMatter {
+    /// PM1.0 concentration
+    ///
+    /// This may not be supported by all sensors.
+    pub pm1_0: Option<f32>,
+
+    /// PM2.5 concentration
+    pub pm2_5: f32,
+
+    /// PM10 concentration
+    pub pm10: f32,
+}
+
+/// Contains functionality that a particulate matter sensor must be able to do.
+///
+/// These sensors have a fan, which is put to sleep when the driver is dropped.
+pub trait ParticulateSensor: Sized {
+    /// Time the fan must run for, before the readings are reliable.
+    const WARMUP_TIME: Duration;
+
+    /// Initialize the driver, wake the sensor up and start its fan.
+    ///
+    /// # Errors
+    /// Upon a communication error, an `Err(..)` value will be returned.
+    fn new_with_driver(driver: UartDriver<'static>) -> OsResult<Self>;
+
+    /// Request and read a single measurement.
+    ///
+    /// # Errors
+    /// Upon a communication error, or if the received frame is invalid, an `Err(..)` value will be returned.
+    fn read(&mut self) -> OsResult<ParticulateMatter>;
+}
@@ -0,0 +1,135 @@
+//! Driver for the Plantower PMS5003 particulate matter sensor.
+//!
+//! ## Operation mode
+//! The sensor is switched into passive mode, so it only sends a measurement when requested.
+//! The fan is put to sleep when the driver is dropped.
+//!
+//! These sensors work over the UART protocol (9600 baud).
+
+use super::particulate::{ParticulateMatter, ParticulateSensor};
+use crate::{
+    re_esp,
+    sysc::{OsError, OsResult, ReportableError},
+};
+use esp_idf_svc::hal::uart::UartDriver;
+use std::{thread::sleep, time::Duration};
+
+/// Length of a data frame, including the header and checksum.
+const FRAME_LEN: usize = 32;
+/// Start of every frame.
+const FRAME_HEADER: [u8; 2] = [0x42, 0x4D];
+
+/// Commands for PMS5003 sensors.
+#[derive(Clone, Copy)]
+enum Command {
+    /// Stop the fan and enter sleep mode
+    Sleep,
+
+    /// Wake up and start the fan
+    WakeUp,
+
+    /// Only send measurements when requested
+    PassiveMode,
+
+    /// Request a measurement in passive mode
+    Read,
+}
+
+/// Driver handle for PMS5003 sensors.
+pub struct Pms5003(UartDriver<'static>);
+
+impl Pms5003 {
+    const UART_TIMEOUT: u32 = 1000;
+    const CMD_WAIT_TIME: u64 = 100;
+
+    fn write(&self, command: Command) -> OsResult<()> {
+        re_esp!(self.0.write(&command.serialize()), UartIo)?;
+        sleep(Duration::from_millis(Self::CMD_WAIT_TIME));
+
+        Ok(())
+    }
+}
+
+impl ParticulateSensor for Pms5003 {
+    const WARMUP_TIME: Duration = Duration::from_secs(30);
+
+    fn new_with_driver(driver: UartDriver<'static>) -> OsResult<Self> {
+        log::debug!("Loading driver");
+        let dev = Self(driver);
+
+        dev.write(Command::WakeUp)?;
+        dev.write(Command::PassiveMode)?;
+
+        Ok(dev)
+    }
+
+    fn read(&mut self) -> OsResult<ParticulateMatter> {
+        // discard acknowledgements and frames sent before passive mode was entered
+        re_esp!(self.0.clear_rx(), UartIo)?;
+        self.write(Command::Read)?;
+
+        let mut frame = [0u8; FRAME_LEN];
+        let len = re_esp!(self.0.read(&mut frame, Self::UART_TIMEOUT), UartIo)?;
+        if len != FRAME_LEN {
+            return Err(OsError::SensorFrame);
+        }
+
+        parse_frame(&frame)
+    }
+}
+
+impl Drop for Pms5003 {
+    fn drop(&mut self) {
+        log::debug!("Putting sensor to sleep");
+        self.write(Command::Sleep)
+            .report("Failed to put sensor to sleep");
+    }
+}
+
+impl Command {
+    /// Serialize the command into a frame for UART transmission.
+    ///
+    /// The frame ends with a checksum, which is the sum of all preceding bytes.
+    fn serialize(self) -> [u8; 7] {
+        let (cmd, data) = match self {
+            Self::Sleep => (0xE4, 0x00),
+            Self::WakeUp => (0xE4, 0x01),
+            Self::PassiveMode => (0xE1, 0x00),
+            Self::Read => (0xE2, 0x00),
+        };
+
+        let [h0, h1] = FRAME_HEADER;
+        let [c0, c1] = checksum(&[h0, h1, cmd, 0x00, data]).to_be_bytes();
+
+        [h0, h1, cmd, 0x00, data, c0, c1]
+    }
+}
+
+/// Parse a data frame.
+///
+/// The frame contains 13 big-endian data words. The first three are concentrations measured under
+/// "standard particle" conditions (CF=1), the next three use the atmospheric environment.
+/// The latter ones are returned, as recommended by the manufacturer for outdoor use.
+fn parse_frame(frame: &[u8; FRAME_LEN]) -> OsResult<ParticulateMatter> {
+    let word = |i: usize| u16::from_be_bytes([frame[i], frame[i + 1]]);
+
+    if frame[..2] != FRAME_HEADER || usize::from(word(2)) != FRAME_LEN - 4 {
+        return Err(OsError::SensorFrame);
+    }
+
+    if checksum(&frame[..FRAME_LEN - 2]) != word(FRAME_LEN - 2) {
+        return Err(OsError::SensorFrame);
+    }
+
+    Ok(ParticulateMatter {
+        pm1_0: Some(f32::from(word(10))),
+        pm2_5: f32::from(word(12)),
+        pm10: f32::from(word(14)),
+    })
+}
+
+/// Calculate the checksum, which is the sum of all bytes.
+fn checksum(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0u16, |sum, byte| sum.wrapping_add(u16::from(*byte)))
+}
@@ -0,0 +1,363 @@
+//! Driver for Sensirion SCD41 CO2 sensors.
+//!
+//! ## Measurement mode
+//! This driver only uses single-shot measurements, which are suited for deep-sleep duty cycles.
+//! The SCD40 does not implement single-shot measurements, so it's not supported.
+//! A measurement takes 5 seconds, so it's started using [`start_measurement()`](Scd4x::start_measurement)
+//! and the result is collected later using [`read_measurement()`](Scd4x::read_measurement).
+//! This allows the firmware to do other work in the meantime.
+//!
+//! The sensor is powered down when the driver is dropped. The first measurement after waking it up
+//! is not accurate, so it's discarded and another one is taken, which makes reading the result take
+//! up to 10 seconds.
+//!
+//! These sensors work over the I2C protocol.
+
+use crate::sysc::{OsError, OsResult, ReportableError};
+use esp_idf_svc::hal::i2c::I2cDriver;
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Commands for SCD41 sensors.
+#[derive(Clone, Copy)]
+enum Command {
+    /// Wake the sensor up from power-down mode
+    WakeUp,
+
+    /// Put the sensor into power-down mode
+    PowerDown,
+
+    /// Read the serial number of the sensor
+    GetSerialNumber,
+
+    /// Start a single-shot measurement of CO2, temperature and humidity
+    MeasureSingleShot,
+
+    /// Check whether a measurement result is available
+    GetDataReadyStatus,
+
+    /// Read the last measurement result
+    ReadMeasurement,
+
+    /// Set the ambient pressure used for compensation (hPa)
+    SetAmbientPressure(u16),
+
+    /// Set the altitude used for compensation, if no ambient pressure is given (m)
+    SetSensorAltitude(u16),
+
+    /// Read the altitude setting
+    GetSensorAltitude,
+
+    /// Enable or disable automatic self-calibration
+    SetAutomaticSelfCalibrationEnabled(bool),
+
+    /// Read the automatic self-calibration setting
+    GetAutomaticSelfCalibrationEnabled,
+
+    /// Store the current settings in the EEPROM
+    PersistSettings,
+}
+
+/// Driver handle for SCD41 sensors.
+pub struct Scd4x<'s> {
+    /// I2C driver handle for communication with the sensor.
+    i2c: &'s mut I2cDriver<'static>,
+
+    /// When the pending measurement has been started.
+    started: Option<Instant>,
+
+    /// Whether the next measurement is the first one after waking the sensor up, which must be discarded.
+    discard_next: bool,
+}
+
+impl<'s> Scd4x<'s> {
+    /// Known default address
+    pub const DEV_ADDR: u8 = 0x62;
+
+    const BUS_TIMEOUT: u32 = 1000;
+    /// Duration of a single-shot measurement.
+    const MEASUREMENT_TIME: Duration = Duration::from_secs(5);
+
+    /// Initialize the driver with the given I2C driver handle.
+    ///
+    /// The sensor is woken up from power-down mode, in case it was left in it.
+    ///
+    /// # Errors
+    /// Returns an error if the sensor does not respond.
+    pub fn new_with_driver(driver: &'s mut I2cDriver<'static>) -> OsResult<Self> {
+        log::debug!("Loading driver");
+        let mut dev = Self {
+            i2c: driver,
+            started: None,
+            discard_next: true,
+        };
+
+        // The sensor does not acknowledge this command, so an error is expected.
+        // In that case, the wait for the command to execute must be done here.
+        if dev.write(Command::WakeUp).is_err() {
+            sleep(Command::WakeUp.execution_time());
+        }
+
+        let mut serial = [0u8; 9];
+        dev.write_read(Command::GetSerialNumber, &mut serial)?;
+        let serial = Self::decode_words::<3>(&serial)?;
+        log::debug!(
+            "Serial number: {:04X}{:04X}{:04X}",
+            serial[0],
+            serial[1],
+            serial[2]
+        );
+
+        Ok(dev)
+    }
+
+    /// Enable or disable automatic self-calibration.
+    ///
+    /// Self-calibration assumes that the sensor is exposed to fresh air (~400ppm) at least once a week.
+    /// The setting is only written to the EEPROM if it differs from the current one.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn set_self_calibration(&mut self, enabled: bool) -> OsResult<()> {
+        let mut buffer = [0u8; 3];
+        self.write_read(Command::GetAutomaticSelfCalibrationEnabled, &mut buffer)?;
+
+        if (Self::decode_words::<1>(&buffer)?[0] != 0) == enabled {
+            return Ok(());
+        }
+
+        log::debug!("Setting self-calibration to {enabled}");
+        self.write(Command::SetAutomaticSelfCalibrationEnabled(enabled))?;
+        self.persist_settings()
+    }
+
+    /// Set the altitude of the sensor in meters, which is used for compensation if
+    /// no ambient pressure is given.
+    ///
+    /// The setting is only written to the EEPROM if it differs from the current one.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn set_altitude(&mut self, altitude: u16) -> OsResult<()> {
+        let mut buffer = [0u8; 3];
+        self.write_read(Command::GetSensorAltitude, &mut buffer)?;
+
+        if Self::decode_words::<1>(&buffer)?[0] == altitude {
+            return Ok(());
+        }
+
+        log::debug!("Setting altitude to {altitude}m");
+        self.write(Command::SetSensorAltitude(altitude))?;
+        self.persist_settings()
+    }
+
+    /// Set the ambient pressure in hPa for compensating the next measurement.
+    ///
+    /// This overrides the altitude compensation.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn set_ambient_pressure(&mut self, pressure: u16) -> OsResult<()> {
+        self.write(Command::SetAmbientPressure(pressure))
+    }
+
+    /// Start a single-shot measurement.
+    ///
+    /// # Errors
+    /// Returns an error if the communication with the sensor fails.
+    pub fn start_measurement(&mut self) -> OsResult<()> {
+        self.write(Command::MeasureSingleShot)?;
+        self.started = Some(Instant::now());
+
+        Ok(())
+    }
+
+    /// Read the CO2 concentration in ppm, measured by [`start_measurement()`](Self::start_measurement).
+    ///
+    /// The sensor measures temperature and humidity as well, but these are affected by its
+    /// self-heating, so they're not used.
+    ///
+    /// If the measurement is still in progress, the caller is blocked until it finishes.
+    /// If it's the first one after waking the sensor up, it's discarded and another one is awaited.
+    ///
+    /// # Errors
+    /// Returns an error if no measurement was started, the result is not ready
+    /// or the communication with the sensor fails.
+    pub fn read_measurement(&mut self) -> OsResult<u16> {
+        let co2 = self.collect_measurement()?;
+
+        if !std::mem::take(&mut self.discard_next) {
+            return Ok(co2);
+        }
+
+        log::debug!("Discarding first measurement after wake-up ({co2}ppm)");
+        self.start_measurement()?;
+        self.collect_measurement()
+    }
+
+    /// Wait for the pending measurement to finish and read its CO2 concentration in ppm.
+    fn collect_measurement(&mut self) -> OsResult<u16> {
+        let started = self.started.take().ok_or(OsError::SensorNotReady)?;
+        sleep(Self::MEASUREMENT_TIME.saturating_sub(started.elapsed()));
+
+        let mut status = [0u8; 3];
+        self.write_read(Command::GetDataReadyStatus, &mut status)?;
+
+        // the lower 11 bits are zero, if the data is not ready
+        if Self::decode_words::<1>(&status)?[0].trailing_zeros() >= 11 {
+            return Err(OsError::SensorNotReady);
+        }
+
+        let mut buffer = [0u8; 9];
+        self.write_read(Command::ReadMeasurement, &mut buffer)?;
+        let [co2, _temperature, _humidity] = Self::decode_words::<3>(&buffer)?;
+
+        Ok(co2)
+    }
+
+    /// Store the current settings in the EEPROM.
+    ///
+    /// The EEPROM has a limited number of write cycles, so this should only be called if a setting changes.
+    fn persist_settings(&mut self) -> OsResult<()> {
+        self.write(Command::PersistSettings)?;
+        sleep(Duration::from_millis(800));
+
+        Ok(())
+    }
+
+    /// Split a response into 16-bit words, while checking the CRC of each one.
+    ///
+    /// Every word is followed by it's CRC byte.
+    fn decode_words<const N: usize>(buffer: &[u8]) -> OsResult<[u16; N]> {
+        let mut words = [0u16; N];
+
+        for (word, chunk) in words.iter_mut().zip(buffer.chunks_exact(3)) {
+            if crc8(&chunk[..2]) != chunk[2] {
+                return Err(OsError::I2cCrc(Self::DEV_ADDR));
+            }
+
+            *word = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+
+        Ok(words)
+    }
+
+    /// Send a non-returning command to the sensor and wait until it's executed.
+    ///
+    /// If the command returns data, use [`write_read()`](Self::write_read) instead.
+    fn write(&mut self, command: Command) -> OsResult<()> {
+        let (cmd, len) = command.serialize();
+
+        OsError::from_i2c_writeop(
+            self.i2c
+                .write(Self::DEV_ADDR, &cmd[..len], Self::BUS_TIMEOUT),
+            Self::DEV_ADDR,
+            &cmd[..2],
+            false,
+        )?;
+
+        sleep(command.execution_time());
+        Ok(())
+    }
+
+    /// Send a command to the sensor and read the response into the provided buffer.
+    ///
+    /// The response can only be read after the command is executed, so this is done in two steps.
+    /// If the command does not return data, use [`write()`](Self::write) instead.
+    fn write_read(&mut self, command: Command, buffer: &mut [u8]) -> OsResult<()> {
+        self.write(command)?;
+
+        OsError::from_i2c_writeop(
+            self.i2c.read(Self::DEV_ADDR, buffer, Self::BUS_TIMEOUT),
+            Self::DEV_ADDR,
+            &command.serialize().0[..2],
+            true,
+        )
+    }
+}
+
+impl Drop for Scd4x<'_> {
+    fn drop(&mut self) {
+        log::debug!("Powering down");
+        self.write(Command::PowerDown)
+            .report("Failed to power down sensor");
+    }
+}
+
+impl Command {
+    /// Serialize the command into raw bytes and a length.
+    ///
+    /// Commands with an argument are followed by a 16-bit word and it's CRC.
+    const fn serialize(self) -> ([u8; 5], usize) {
+        let (code, arg) = match self {
+            Self::WakeUp => (0x36F6, None),
+            Self::PowerDown => (0x36E0, None),
+            Self::GetSerialNumber => (0x3682, None),
+            Self::MeasureSingleShot => (0x219D, None),
+            Self::GetDataReadyStatus => (0xE4B8, None),
+            Self::ReadMeasurement => (0xEC05, None),
+            Self::SetAmbientPressure(hpa) => (0xE000, Some(hpa)),
+            Self::SetSensorAltitude(m) => (0x2427, Some(m)),
+            Self::GetSensorAltitude => (0x2322, None),
+            Self::SetAutomaticSelfCalibrationEnabled(enabled) => (0x2416, Some(enabled as u16)),
+            Self::GetAutomaticSelfCalibrationEnabled => (0x2313, None),
+            Self::PersistSettings => (0x3615, None),
+        };
+
+        let [c0, c1] = u16::to_be_bytes(code);
+
+        match arg {
+            Some(arg) => {
+                let [a0, a1] = arg.to_be_bytes();
+                ([c0, c1, a0, a1, crc8(&[a0, a1])], 5)
+            }
+            None => ([c0, c1, 0, 0, 0], 2),
+        }
+    }
+
+    /// Time needed by the sensor to execute the command, as given in the datasheet.
+    ///
+    /// The single-shot measurement is not included, as it's awaited separately.
+    const fn execution_time(self) -> Duration {
+        match self {
+            Self::WakeUp => Duration::from_millis(30),
+            Self::GetDataReadyStatus
+            | Self::ReadMeasurement
+            | Self::GetSerialNumber
+            | Self::SetAmbientPressure(..)
+            | Self::SetSensorAltitude(..)
+            | Self::GetSensorAltitude
+            | Self::SetAutomaticSelfCalibrationEnabled(..)
+            | Self::GetAutomaticSelfCalibrationEnabled
+            | Self::PowerDown
+            | Self::MeasureSingleShot => Duration::from_millis(1),
+            Self::PersistSettings => Duration::ZERO, /* awaited in `persist_settings()` */
+        }
+    }
+}
+
+/// Calculate the Sensirion CRC-8 checksum (polynomial `0x31`, initial value `0xFF`).
+const fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xFF;
+    let mut i = 0;
+
+    while i < data.len() {
+        crc ^= data[i];
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+
+        i += 1;
+    }
+
+    crc
+}
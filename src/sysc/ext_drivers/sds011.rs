@@ -0,0 +1,146 @@
+//! Driver for the Nova Fitness SDS011 particulate matter sensor.
+//!
+//! ## Operation mode
+//! The sensor is switched into query mode, so it only sends a measurement when requested.
+//! The fan is put to sleep when the driver is dropped.
+//!
+//! These sensors work over the UART protocol (9600 baud).
+
+use super::particulate::{ParticulateMatter, ParticulateSensor};
+use crate::{
+    re_esp,
+    sysc::{OsError, OsResult, ReportableError},
+};
+use esp_idf_svc::hal::uart::UartDriver;
+use std::{thread::sleep, time::Duration};
+
+/// Length of a data frame, including the head and tail.
+const FRAME_LEN: usize = 10;
+/// Length of a command frame, including the head and tail.
+const COMMAND_LEN: usize = 19;
+/// First byte of every frame.
+const FRAME_HEAD: u8 = 0xAA;
+/// Last byte of every frame.
+const FRAME_TAIL: u8 = 0xAB;
+/// Command ID of data frames.
+const DATA_FRAME_ID: u8 = 0xC0;
+
+/// Commands for SDS011 sensors.
+#[derive(Clone, Copy)]
+enum Command {
+    /// Stop the fan and enter sleep mode
+    Sleep,
+
+    /// Wake up and start the fan
+    WakeUp,
+
+    /// Only send measurements when requested
+    QueryMode,
+
+    /// Request a measurement in query mode
+    Query,
+}
+
+/// Driver handle for SDS011 sensors.
+pub struct Sds011(UartDriver<'static>);
+
+impl Sds011 {
+    const UART_TIMEOUT: u32 = 1000;
+    const CMD_WAIT_TIME: u64 = 100;
+
+    fn write(&self, command: Command) -> OsResult<()> {
+        re_esp!(self.0.write(&command.serialize()), UartIo)?;
+        sleep(Duration::from_millis(Self::CMD_WAIT_TIME));
+
+        Ok(())
+    }
+}
+
+impl ParticulateSensor for Sds011 {
+    const WARMUP_TIME: Duration = Duration::from_secs(30);
+
+    fn new_with_driver(driver: UartDriver<'static>) -> OsResult<Self> {
+        log::debug!("Loading driver");
+        let dev = Self(driver);
+
+        dev.write(Command::WakeUp)?;
+        dev.write(Command::QueryMode)?;
+
+        Ok(dev)
+    }
+
+    fn read(&mut self) -> OsResult<ParticulateMatter> {
+        // discard replies to previous commands
+        re_esp!(self.0.clear_rx(), UartIo)?;
+        self.write(Command::Query)?;
+
+        let mut frame = [0u8; FRAME_LEN];
+        let len = re_esp!(self.0.read(&mut frame, Self::UART_TIMEOUT), UartIo)?;
+        if len != FRAME_LEN {
+            return Err(OsError::SensorFrame);
+        }
+
+        parse_frame(&frame)
+    }
+}
+
+impl Drop for Sds011 {
+    fn drop(&mut self) {
+        log::debug!("Putting sensor to sleep");
+        self.write(Command::Sleep)
+            .report("Failed to put sensor to sleep");
+    }
+}
+
+impl Command {
+    /// Serialize the command into a frame for UART transmission.
+    ///
+    /// The command is addressed to all sensors (`0xFFFF`), followed by a checksum of the payload.
+    fn serialize(self) -> [u8; COMMAND_LEN] {
+        let (cmd, data): (u8, &[u8]) = match self {
+            Self::Sleep => (6, &[1, 0]),
+            Self::WakeUp => (6, &[1, 1]),
+            Self::QueryMode => (2, &[1, 1]),
+            Self::Query => (4, &[]),
+        };
+
+        let mut frame = [0u8; COMMAND_LEN];
+        frame[0] = FRAME_HEAD;
+        frame[1] = 0xB4;
+        frame[2] = cmd;
+        frame[3..3 + data.len()].copy_from_slice(data);
+        frame[15] = 0xFF;
+        frame[16] = 0xFF;
+        frame[17] = checksum(&frame[2..17]);
+        frame[18] = FRAME_TAIL;
+
+        frame
+    }
+}
+
+/// Parse a data frame.
+///
+/// The frame contains the PM2.5 and PM10 concentrations in 0.1µg/m³ as little-endian words,
+/// followed by the sensor ID and a checksum.
+fn parse_frame(frame: &[u8; FRAME_LEN]) -> OsResult<ParticulateMatter> {
+    let word = |i: usize| u16::from_le_bytes([frame[i], frame[i + 1]]);
+
+    if frame[0] != FRAME_HEAD || frame[1] != DATA_FRAME_ID || frame[9] != FRAME_TAIL {
+        return Err(OsError::SensorFrame);
+    }
+
+    if checksum(&frame[2..8]) != frame[8] {
+        return Err(OsError::SensorFrame);
+    }
+
+    Ok(ParticulateMatter {
+        pm1_0: None,
+        pm2_5: f32::from(word(2)) / 10.0,
+        pm10: f32::from(word(4)) / 10.0,
+    })
+}
+
+/// Calculate the checksum, which is the lower 8 bits of the sum of all bytes.
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
@@ -0,0 +1,213 @@
+//! Lightning detection using an AS3935 sensor.
+//!
+//! The sensor keeps listening while the node sleeps. When it detects an event, it raises its IRQ pin,
+//! which wakes the MCU up (*ext0* wake-up):
+//! - Lightning is recorded in RTC memory and a full cycle is done, so that a notification can be sent right away.
+//!   Further strikes within [`LIGHTNING_NOTIFY_HOLDOFF`] are only recorded, to not drain the battery during a storm.
+//! - Disturbers and noise are ignored and the node goes back to sleep. The noise floor is raised on every noise event.
+//!
+//! All strikes since the last report are aggregated into the regular measurements.
+
+use crate::{
+    config::{
+        LIGHTNING_INDOOR, LIGHTNING_MASK_DISTURBERS, LIGHTNING_NOISE_FLOOR,
+        LIGHTNING_NOTIFY_HOLDOFF,
+    },
+    re_esp,
+    sysc::{
+        channels::Channels,
+        ext_drivers::{As3935, LightningEvent},
+        power::{get_wakeup_cause, WakeupCause},
+        rtc::RtcCell,
+        schedule::now_us,
+        OsResult,
+    },
+};
+use esp_idf_svc::{
+    hal::{
+        gpio::{AnyInputPin, Pin},
+        i2c::I2cDriver,
+    },
+    sys::{
+        esp, esp_sleep_enable_ext0_wakeup, esp_sleep_pd_config,
+        esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH, esp_sleep_pd_option_t_ESP_PD_OPTION_ON,
+    },
+};
+
+/// Lightning state, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static STATE: RtcCell<State> = RtcCell::new(State::new());
+
+/// IRQ pin of the sensor, which wakes the MCU up.
+pub struct LightningIrq(AnyInputPin<'static>);
+
+/// A single lightning strike.
+#[derive(Clone, Copy)]
+pub struct Strike {
+    /// Estimated distance of the storm front in km, `None` if it's out of range.
+    pub distance: Option<u8>,
+
+    /// Energy of the strike, this has no physical meaning.
+    pub energy: u32,
+}
+
+/// Lightning detection state.
+#[derive(Clone, Copy)]
+struct State {
+    /// Number of strikes since the last report.
+    strikes: u32,
+
+    /// Closest estimated distance since the last report.
+    closest: Option<u8>,
+
+    /// Strike, for which a notification should be sent.
+    pending: Option<Strike>,
+
+    /// When the last notification was sent in microseconds.
+    last_notification_us: Option<u64>,
+}
+
+impl LightningIrq {
+    /// Wrap the IRQ pin.
+    pub const fn new(pin: AnyInputPin<'static>) -> Self {
+        Self(pin)
+    }
+
+    /// Enable waking up when the IRQ pin goes high during the next deep sleep.
+    ///
+    /// # Errors
+    /// Returns an error if the wake-up source cannot be configured.
+    pub fn arm(&self) -> OsResult<()> {
+        // SAFETY: The pin is owned by this driver.
+        unsafe {
+            re_esp!(
+                esp!(esp_sleep_pd_config(
+                    esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH,
+                    esp_sleep_pd_option_t_ESP_PD_OPTION_ON
+                )),
+                GpioInit
+            )?;
+            re_esp!(
+                esp!(esp_sleep_enable_ext0_wakeup(self.0.pin(), 1)),
+                GpioInit
+            )
+        }
+    }
+}
+
+/// Set up the sensor after a power loss, or handle the event that has woken the MCU up.
+///
+/// Returns whether the MCU was woken up by an event, that does not need a full cycle.
+///
+/// # Errors
+/// Returns an error if the communication with the sensor fails.
+pub fn handle_wakeup(i2c: &mut I2cDriver<'static>) -> OsResult<bool> {
+    let mut sensor = As3935::new_with_driver(i2c);
+
+    match get_wakeup_cause() {
+        WakeupCause::None => {
+            log::debug!("Setting up lightning sensor");
+            sensor.reset()?;
+            sensor.configure(
+                LIGHTNING_INDOOR,
+                LIGHTNING_NOISE_FLOOR,
+                LIGHTNING_MASK_DISTURBERS,
+            )?;
+
+            Ok(false)
+        }
+        WakeupCause::Ext0 => match sensor.read_event()? {
+            LightningEvent::Lightning { distance, energy } => {
+                log::info!("Lightning detected ({distance:?}km, energy {energy})");
+                Ok(!record(Strike { distance, energy }))
+            }
+            LightningEvent::NoiseTooHigh => {
+                match sensor.raise_noise_floor()? {
+                    Some(level) => {
+                        log::warn!("Lightning sensor noise too high, raised floor to {level}");
+                    }
+                    None => log::warn!("Lightning sensor noise too high"),
+                }
+
+                Ok(true)
+            }
+            event => {
+                log::debug!("Ignoring lightning sensor event {event:?}");
+                Ok(true)
+            }
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Returns the strike, for which a notification should be sent.
+pub fn pending_notification() -> Option<Strike> {
+    STATE.get().pending
+}
+
+/// Mark the pending notification as sent.
+pub fn notification_sent() {
+    STATE.update(|state| {
+        state.pending = None;
+        state.last_notification_us = Some(now_us());
+    });
+}
+
+/// Add the strikes recorded since the last report to `channels`.
+///
+/// The strikes are kept until [`reset()`] is called.
+#[allow(clippy::cast_precision_loss)]
+pub fn collect(channels: &mut Channels) {
+    let state = STATE.get();
+
+    channels.push("lightning_strikes", state.strikes as f32, "");
+    if let Some(distance) = state.closest {
+        channels.push("lightning_distance", f32::from(distance), "km");
+    }
+}
+
+/// Clear the strikes recorded since the last report.
+pub fn reset() {
+    STATE.update(|state| {
+        state.strikes = 0;
+        state.closest = None;
+    });
+}
+
+/// Record a strike.
+///
+/// Returns whether a notification should be sent for it.
+fn record(strike: Strike) -> bool {
+    let now = now_us();
+    let mut notify = false;
+
+    STATE.update(|state| {
+        state.strikes += 1;
+        state.closest = match (state.closest, strike.distance) {
+            (Some(closest), Some(distance)) => Some(closest.min(distance)),
+            (closest, distance) => closest.or(distance),
+        };
+
+        notify = state.pending.is_none()
+            && state.last_notification_us.is_none_or(|last| {
+                u128::from(now.saturating_sub(last)) >= LIGHTNING_NOTIFY_HOLDOFF.as_micros()
+            });
+
+        if notify {
+            state.pending = Some(strike);
+        }
+    });
+
+    notify
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            strikes: 0,
+            closest: None,
+            pending: None,
+            last_notification_us: None,
+        }
+    }
+}
@@ -1,9 +1,16 @@
 pub mod battery;
-#[cfg(debug_assertions)]
 pub mod brownout;
+pub mod button;
+pub mod channels;
+#[cfg(feature = "solar")]
+pub mod charger;
+pub mod clock;
+pub mod console;
 mod error;
 pub mod ext_drivers;
 pub mod ledctl;
+#[cfg(feature = "as3935")]
+pub mod lightning;
 pub mod logging;
 mod macros;
 pub mod net;
@@ -11,10 +18,23 @@ pub mod nvs;
 pub mod ota;
 pub mod panic;
 pub mod periph;
+pub mod phases;
+pub mod pm;
 pub mod power;
+#[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+pub mod pulse;
+mod rtc;
+pub mod safe_mode;
+pub mod schedule;
+pub mod stats;
+pub mod supervisor;
+pub mod thermal;
 pub mod usbctl;
+pub mod wake;
+#[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+pub mod wake_stub;
 
-pub use error::{OsError, ReportableError};
+pub use error::{ErrorCategory, OsError, ReportableError};
 pub type OsResult<T> = ::std::result::Result<T, OsError>;
 
 #[cfg(debug_assertions)]
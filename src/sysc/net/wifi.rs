@@ -1,7 +1,10 @@
 use crate::{
     config::WIFI_COUNTRY_CODE,
     re_esp,
-    sysc::{OsError, OsResult},
+    sysc::{
+        phases::{self, Phase},
+        pm, OsError, OsResult,
+    },
 };
 use esp_idf_svc::{
     eventloop::{EspEventLoop, EspEventSource, EspSystemEventLoop, System, Wait},
@@ -12,8 +15,8 @@ use esp_idf_svc::{
     },
     netif::{EspNetif, IpEvent, NetifConfiguration},
     sys::{
-        esp, esp_wifi_scan_start, esp_wifi_set_country_code, esp_wifi_set_storage,
-        wifi_storage_t_WIFI_STORAGE_RAM, EspError,
+        esp, esp_wifi_scan_start, esp_wifi_set_country_code, esp_wifi_set_max_tx_power,
+        esp_wifi_set_storage, wifi_storage_t_WIFI_STORAGE_RAM, EspError,
     },
     wifi::{
         AccessPointInfo, ClientConfiguration, Configuration, EspWifi, PmfConfiguration, ScanMethod,
@@ -68,6 +71,14 @@ impl WiFi {
         })
     }
 
+    pub fn set_max_tx_power(&self, dbm: i8) -> OsResult<()> {
+        // the driver uses units of 0.25dBm
+        re_esp!(
+            esp!(unsafe { esp_wifi_set_max_tx_power(dbm.saturating_mul(4)) }),
+            WifiParam
+        )
+    }
+
     pub fn scan(&mut self) -> OsResult<heapless::Vec<AccessPointInfo, MAX_NET_SCAN>> {
         // Due to a bug in `esp-idf-svc` causing `ScanModes` to not be properly converted
         // to `wifi_scan_type_t_*` this alternative is faster.
@@ -104,15 +115,23 @@ impl WiFi {
 
         log::debug!("Waiting for connection result");
         // wait until connected
-        self.await_event::<WifiEvent, _, _>(
-            || self.driver.is_connected(),
-            OsError::EventTimeout,
-            timeout,
-        )?;
+        phases::measure(Phase::WifiConnect, || {
+            self.await_event::<WifiEvent, _, _>(
+                || self.driver.is_connected(),
+                OsError::EventTimeout,
+                timeout,
+            )
+        })?;
 
         log::debug!("Waiting for IP address");
         // wait until we get an IP
-        self.await_event::<IpEvent, _, _>(|| self.driver.is_up(), OsError::EventTimeout, timeout)?;
+        phases::measure(Phase::Dhcp, || {
+            self.await_event::<IpEvent, _, _>(
+                || self.driver.is_up(),
+                OsError::EventTimeout,
+                timeout,
+            )
+        })?;
 
         Ok(())
     }
@@ -124,8 +143,7 @@ impl WiFi {
         U: Fn(EspError) -> OsError,
     {
         let wait = re_esp!(Wait::new::<S>(&self.event_loop), EventWaiterInit)?;
-        wait.wait_while(|| matcher().map(|s| !s), Some(timeout))
-            .map_err(err_map)
+        pm::idle(|| wait.wait_while(|| matcher().map(|s| !s), Some(timeout))).map_err(err_map)
     }
 
     pub fn get_ip_info(&self) -> OsResult<esp_idf_svc::ipv4::IpInfo> {
@@ -1,4 +1,12 @@
-use super::{OsError, OsResult};
+use super::{
+    battery::{
+        calibration::{Calibration, CalibrationPoint},
+        health::Baseline,
+    },
+    safe_mode::BootHistory,
+    stats::Stats,
+    OsError, OsResult,
+};
 use crate::re_esp;
 use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
 
@@ -6,6 +14,20 @@ use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
 const NAMESPACE: &str = "pixelweather";
 /// Key name for the last system error.
 const LAST_OS_ERROR_KEY: &str = "last_error";
+/// Key name for the battery calibration.
+const BATTERY_CALIBRATION_KEY: &str = "bat_cal";
+/// Key name for the first point of an unfinished battery calibration.
+const BATTERY_CALIBRATION_POINT_KEY: &str = "bat_cal_point";
+/// Key name for the battery internal resistance checkpoint.
+const BATTERY_RESISTANCE_KEY: &str = "bat_resistance";
+/// Key name for the long-term baseline of the battery internal resistance.
+const BATTERY_RESISTANCE_BASELINE_KEY: &str = "bat_res_base";
+/// Key name for the boot history.
+const BOOT_HISTORY_KEY: &str = "boot_history";
+/// Key name for the number of unreported brownout resets.
+const BROWNOUT_COUNT_KEY: &str = "brownouts";
+/// Key name for the reliability statistics checkpoint.
+const STATS_KEY: &str = "stats";
 
 /// A high-level wrapper/driver for the Non-volatile storage driver.
 ///
@@ -53,6 +75,176 @@ impl NonVolatileStorage {
         self.delete_key(LAST_OS_ERROR_KEY)
     }
 
+    /// Gets the battery calibration of this unit.
+    ///
+    /// Returns [`Option::None`] if the battery was not calibrated.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn get_battery_calibration(&self) -> OsResult<Option<Calibration>> {
+        Ok(re_esp!(self.0.get_u64(BATTERY_CALIBRATION_KEY), NvsRead)?.map(Calibration::from_bits))
+    }
+
+    /// Stores the battery calibration of this unit.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn store_battery_calibration(&self, calibration: Calibration) -> OsResult<()> {
+        re_esp!(
+            self.0
+                .set_u64(BATTERY_CALIBRATION_KEY, calibration.to_bits()),
+            NvsWrite
+        )
+    }
+
+    /// Gets the first point of an unfinished battery calibration.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn get_battery_calibration_point(&self) -> OsResult<Option<CalibrationPoint>> {
+        Ok(
+            re_esp!(self.0.get_u64(BATTERY_CALIBRATION_POINT_KEY), NvsRead)?
+                .map(CalibrationPoint::from_bits),
+        )
+    }
+
+    /// Stores the first point of an unfinished battery calibration.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn store_battery_calibration_point(&self, point: CalibrationPoint) -> OsResult<()> {
+        re_esp!(
+            self.0
+                .set_u64(BATTERY_CALIBRATION_POINT_KEY, point.to_bits()),
+            NvsWrite
+        )
+    }
+
+    /// Deletes the battery calibration and any unfinished calibration point.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn clear_battery_calibration(&self) -> OsResult<()> {
+        re_esp!(self.0.remove(BATTERY_CALIBRATION_KEY), NvsWrite)?;
+        self.clear_battery_calibration_point()
+    }
+
+    /// Deletes the first point of an unfinished battery calibration.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn clear_battery_calibration_point(&self) -> OsResult<()> {
+        re_esp!(self.0.remove(BATTERY_CALIBRATION_POINT_KEY), NvsWrite)?;
+        Ok(())
+    }
+
+    /// Gets the last checkpoint of the battery internal resistance in mOhm.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn get_battery_resistance(&self) -> OsResult<Option<f32>> {
+        Ok(re_esp!(self.0.get_u32(BATTERY_RESISTANCE_KEY), NvsRead)?.map(f32::from_bits))
+    }
+
+    /// Stores a checkpoint of the battery internal resistance in mOhm.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn store_battery_resistance(&self, resistance: f32) -> OsResult<()> {
+        re_esp!(
+            self.0.set_u32(BATTERY_RESISTANCE_KEY, resistance.to_bits()),
+            NvsWrite
+        )
+    }
+
+    /// Gets the long-term baseline of the battery internal resistance.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn get_battery_resistance_baseline(&self) -> OsResult<Option<Baseline>> {
+        Ok(
+            re_esp!(self.0.get_u64(BATTERY_RESISTANCE_BASELINE_KEY), NvsRead)?
+                .map(Baseline::from_bits),
+        )
+    }
+
+    /// Stores the long-term baseline of the battery internal resistance.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn store_battery_resistance_baseline(&self, baseline: Baseline) -> OsResult<()> {
+        re_esp!(
+            self.0
+                .set_u64(BATTERY_RESISTANCE_BASELINE_KEY, baseline.to_bits()),
+            NvsWrite
+        )
+    }
+
+    /// Gets the boot history, used for detecting crash loops.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn get_boot_history(&self) -> OsResult<Option<BootHistory>> {
+        Ok(re_esp!(self.0.get_u32(BOOT_HISTORY_KEY), NvsRead)?.map(BootHistory::from_bits))
+    }
+
+    /// Stores the boot history.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn store_boot_history(&self, history: BootHistory) -> OsResult<()> {
+        re_esp!(
+            self.0.set_u32(BOOT_HISTORY_KEY, history.to_bits()),
+            NvsWrite
+        )
+    }
+
+    /// Gets the number of brownout resets, that were not reported yet.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn get_brownout_count(&self) -> OsResult<Option<u32>> {
+        re_esp!(self.0.get_u32(BROWNOUT_COUNT_KEY), NvsRead)
+    }
+
+    /// Stores the number of brownout resets, that were not reported yet.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn store_brownout_count(&self, count: u32) -> OsResult<()> {
+        re_esp!(self.0.set_u32(BROWNOUT_COUNT_KEY, count), NvsWrite)
+    }
+
+    /// Deletes the number of brownout resets.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn clear_brownout_count(&self) -> OsResult<()> {
+        re_esp!(self.0.remove(BROWNOUT_COUNT_KEY), NvsWrite)?;
+        Ok(())
+    }
+
+    /// Gets the reliability statistics checkpoint.
+    ///
+    /// Returns [`Option::None`] if no statistics were stored, or if they were stored in a shorter layout.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails, e.g. if the stored statistics are longer
+    /// than [`Stats::SIZE`].
+    pub fn get_stats(&self) -> OsResult<Option<Stats>> {
+        let mut buffer = [0u8; Stats::SIZE];
+
+        Ok(re_esp!(self.0.get_blob(STATS_KEY, &mut buffer), NvsRead)?.and_then(Stats::from_bytes))
+    }
+
+    /// Stores the reliability statistics checkpoint.
+    ///
+    /// # Errors
+    /// Returns an error if the underlying NVS driver fails.
+    pub fn store_stats(&self, stats: Stats) -> OsResult<()> {
+        re_esp!(self.0.set_blob(STATS_KEY, &stats.to_bytes()), NvsWrite)
+    }
+
     /// Deletes a value by it's key from the NVS.
     ///
     /// # Errors
@@ -1,20 +1,55 @@
+#[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+use super::PulseInputs;
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+use super::UartPeripherals;
 use super::{
-    initialize_base_parts, BatteryPeripherals, I2cPeripherals, OnboardLedPeripherals,
-    SystemPeripherals, WifiPeripherals,
+    initialize_base_parts, AuxPeripherals, BatteryPeripherals, I2cPeripherals,
+    OnboardLedPeripherals, SleepConfig, SystemPeripherals, WakeButton, WifiPeripherals,
+    DEFAULT_ISOLATED_PINS,
 };
+#[cfg(feature = "solar")]
+use super::{SolarPeripherals, SOLAR_PANEL_DIVIDER};
+use crate::sysc::battery::BatteryConfig;
+#[cfg(feature = "solar")]
+use esp_idf_svc::hal::gpio::Gpio10;
 use esp_idf_svc::hal::{
-    adc::ADC1,
-    gpio::{Gpio17, Gpio3, Gpio5, Gpio8},
+    adc::{attenuation, ADC1},
+    gpio::{Gpio17, Gpio2, Gpio5, Gpio8},
     i2c::I2C1,
 };
 
+/// On-board battery measurement circuit, which halves the battery voltage.
+// Up to 2.1V at the ADC, which needs the highest attenuation.
+const BATTERY_CONFIG: BatteryConfig = BatteryConfig {
+    r1: 100_000., // 100kOhm
+    r2: 100_000., // 100kOhm
+    attenuation: attenuation::DB_12,
+};
+
+/// Deep sleep configuration of the board
+pub const SLEEP_CONFIG: SleepConfig = SleepConfig {
+    isolate: DEFAULT_ISOLATED_PINS,
+    hold: &[(17, false)], // onboard LED off
+    rtc_periph_off: true,
+};
+
+/// Wake-up button of the board (BOOT button)
+pub const WAKE_BUTTON: WakeButton = WakeButton {
+    pin: 0,
+    active_low: true,
+};
+
+/// Pin where the solar panel voltage divider is connected
+#[cfg(feature = "solar")]
+pub type SolarPanelPin = Gpio10<'static>;
+
 impl
     SystemPeripherals<
         I2C1<'static>,
         Gpio8<'static>,
         Gpio5<'static>,
         ADC1<'static>,
-        Gpio3<'static>,
+        Gpio2<'static>,
         Gpio17<'static>,
     >
 {
@@ -29,7 +64,9 @@ impl
             },
             battery: BatteryPeripherals {
                 adc: peripherals.adc1,
-                pin: peripherals.pins.gpio3,
+                pin: peripherals.pins.gpio2,
+                config: BATTERY_CONFIG,
+                fuel_gauge: None,
             },
             onboard_led: OnboardLedPeripherals {
                 pin: peripherals.pins.gpio17,
@@ -40,6 +77,33 @@ impl
                 sys_loop,
             },
             temp_sensor: peripherals.temp_sensor,
+            aux: AuxPeripherals {
+                #[cfg(feature = "ds18b20")]
+                onewire: peripherals.pins.gpio4.into(),
+                #[cfg(any(feature = "pms5003", feature = "sds011"))]
+                particulate: UartPeripherals {
+                    uart: peripherals.uart1,
+                    tx: peripherals.pins.gpio6.degrade_output(),
+                    rx: peripherals.pins.gpio7.degrade_input(),
+                },
+            },
+            #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+            pulse: PulseInputs {
+                #[cfg(feature = "anemometer")]
+                anemometer: peripherals.pins.gpio1.degrade_input(),
+                #[cfg(feature = "rain-gauge")]
+                rain_gauge: peripherals.pins.gpio14.degrade_input(),
+            },
+            #[cfg(feature = "as3935")]
+            lightning_irq: peripherals.pins.gpio9.degrade_input(),
+            #[cfg(feature = "solar")]
+            solar: SolarPeripherals {
+                panel: peripherals.pins.gpio10,
+                chrg: peripherals.pins.gpio11.degrade_input(),
+                stdby: peripherals.pins.gpio12.degrade_input(),
+                enable: peripherals.pins.gpio13.degrade_output(),
+                divider: SOLAR_PANEL_DIVIDER,
+            },
         }
     }
 }
@@ -1,16 +1,108 @@
 //! System peripherals.
 
+use super::battery::{source::FuelGauge, BatteryConfig};
+#[cfg(feature = "ds18b20")]
+use esp_idf_svc::hal::gpio::AnyIOPin;
+#[cfg(any(
+    feature = "pms5003",
+    feature = "sds011",
+    feature = "anemometer",
+    feature = "rain-gauge",
+    feature = "as3935",
+    feature = "solar"
+))]
+use esp_idf_svc::hal::gpio::AnyInputPin;
+#[cfg(any(feature = "pms5003", feature = "sds011", feature = "solar"))]
+use esp_idf_svc::hal::gpio::AnyOutputPin;
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+use esp_idf_svc::hal::uart::UART1;
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
-    hal::{modem::Modem, peripherals::Peripherals, temp_sensor::TempSensor},
+    hal::{adc::attenuation, modem::Modem, peripherals::Peripherals, temp_sensor::TempSensor},
+};
+
+/// Default battery measurement circuit, used by boards without an on-board one.
+// With these resistors, the maximum ADC input at 4.2V should be 970mV,
+// so 0 attenuation is almost the correct choice. Due to the high resistor values,
+// even if this higher voltage enters the ADC, the current should be very limited,
+// i.e. no damage should be done.
+const DEFAULT_BATTERY_CONFIG: BatteryConfig = BatteryConfig {
+    r1: 1_000_000., // 1MOhm
+    r2: 300_000.,   // 300kOhm
+    attenuation: attenuation::DB_0,
+};
+
+/// Default solar panel measurement circuit, used by all supported boards.
+// Allows measuring up to ~7V, which covers the open-circuit voltage of 6V panels.
+#[cfg(feature = "solar")]
+const SOLAR_PANEL_DIVIDER: BatteryConfig = BatteryConfig {
+    r1: 1_000_000., // 1MOhm
+    r2: 200_000.,   // 200kOhm
+    attenuation: attenuation::DB_2_5,
 };
 
+/// RTC GPIOs used by the common wiring, that are not needed during deep sleep.
+///
+/// Isolating them stops current from leaking into the pads through the external resistors.
+const DEFAULT_ISOLATED_PINS: &[i32] = &[
+    2, // battery voltage divider
+    5, // I2C SDA, pulled up by the sensor boards
+    8, // I2C SCL, pulled up by the sensor boards
+    #[cfg(feature = "ds18b20")]
+    4, // 1-Wire, external pull-up
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    6, // UART TX
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    7, // UART RX
+    #[cfg(feature = "solar")]
+    10, // solar panel voltage divider
+    #[cfg(feature = "solar")]
+    11, // charger CHRG, would draw current through the pull-up while charging
+    #[cfg(feature = "solar")]
+    12, // charger STDBY, would draw current through the pull-up while charged
+];
+
+/// Charger `CE` input of the common wiring, held at its level during deep sleep.
+#[cfg(feature = "solar")]
+pub const CHARGER_ENABLE_PIN: i32 = 13;
+
+/// Deep sleep configuration of a board.
+pub struct SleepConfig {
+    /// RTC GPIOs to isolate during deep sleep
+    pub isolate: &'static [i32],
+
+    /// GPIOs to hold at a level during deep sleep as `(gpio, high)`, e.g. to keep the LED or sensor power off
+    pub hold: &'static [(i32, bool)],
+
+    /// Whether the RTC peripherals can be powered down during deep sleep
+    ///
+    /// They are kept powered anyway, if a wake-up source needs them.
+    pub rtc_periph_off: bool,
+}
+
+/// Button, that wakes the node up from deep sleep.
+pub struct WakeButton {
+    /// RTC GPIO of the button
+    pub pin: i32,
+
+    /// Whether the pin is pulled low while the button is pressed
+    pub active_low: bool,
+}
+
 pub struct SystemPeripherals<I2C, SclPin, SdaPin, ADC, ADCPin, LedPin> {
     pub i2c: I2cPeripherals<I2C, SclPin, SdaPin>,
     pub battery: BatteryPeripherals<ADC, ADCPin>,
     pub onboard_led: OnboardLedPeripherals<LedPin>,
     pub wifi: WifiPeripherals,
     pub temp_sensor: TempSensor<'static>,
+    pub aux: AuxPeripherals,
+    #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+    pub pulse: PulseInputs,
+    /// IRQ pin of the AS3935 lightning sensor, must be an RTC GPIO
+    #[cfg(feature = "as3935")]
+    pub lightning_irq: AnyInputPin<'static>,
+    #[cfg(feature = "solar")]
+    pub solar: SolarPeripherals<SolarPanelPin>,
 }
 
 pub struct I2cPeripherals<I2C, SclPin, SdaPin> {
@@ -22,6 +114,9 @@ pub struct I2cPeripherals<I2C, SclPin, SdaPin> {
 pub struct BatteryPeripherals<ADC, Pin> {
     pub adc: ADC,
     pub pin: Pin,
+    pub config: BatteryConfig,
+    /// Fuel gauge on the I2C bus, used instead of the ADC measurement
+    pub fuel_gauge: Option<FuelGauge>,
 }
 
 pub struct OnboardLedPeripherals<LedPin> {
@@ -34,6 +129,55 @@ pub struct WifiPeripherals {
     pub sys_loop: EspSystemEventLoop,
 }
 
+/// Peripherals used by optional sensors, enabled using features.
+pub struct AuxPeripherals {
+    /// 1-Wire bus for DS18B20 probes
+    #[cfg(feature = "ds18b20")]
+    pub onewire: AnyIOPin<'static>,
+
+    /// UART for PMS5003/SDS011 particulate matter sensors
+    #[cfg(any(feature = "pms5003", feature = "sds011"))]
+    pub particulate: UartPeripherals,
+}
+
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+pub struct UartPeripherals {
+    pub uart: UART1<'static>,
+    pub tx: AnyOutputPin<'static>,
+    pub rx: AnyInputPin<'static>,
+}
+
+/// Solar charger inputs.
+#[cfg(feature = "solar")]
+pub struct SolarPeripherals<PanelPin> {
+    /// Output of the panel voltage divider, must be on the same ADC unit as the battery
+    pub panel: PanelPin,
+
+    /// Charger `CHRG` status output
+    pub chrg: AnyInputPin<'static>,
+
+    /// Charger `STDBY` status output
+    pub stdby: AnyInputPin<'static>,
+
+    /// Charger `CE` input, charging is disabled while it's low (see [`CHARGER_ENABLE_PIN`])
+    pub enable: AnyOutputPin<'static>,
+
+    /// Panel voltage divider
+    pub divider: BatteryConfig,
+}
+
+/// Inputs for counting pulses during deep sleep, these must be RTC GPIOs.
+#[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+pub struct PulseInputs {
+    /// Reed-switch anemometer
+    #[cfg(feature = "anemometer")]
+    pub anemometer: AnyInputPin<'static>,
+
+    /// Tipping-bucket rain gauge
+    #[cfg(feature = "rain-gauge")]
+    pub rain_gauge: AnyInputPin<'static>,
+}
+
 fn initialize_base_parts() -> (Peripherals, EspSystemEventLoop) {
     log::debug!("Initializing base peripherals");
     let peripherals = Peripherals::take().expect("Failed to initialize peripherals");
@@ -55,3 +199,38 @@ mod xiao_s3;
 
 #[cfg(feature = "arduino-nano-esp32")]
 mod arduino_nano_esp32;
+
+#[cfg(all(
+    feature = "solar",
+    any(
+        feature = "lilygo-t7s3",
+        not(any(
+            feature = "lilygo-t7s3",
+            feature = "xiao-s3",
+            feature = "arduino-nano-esp32"
+        ))
+    )
+))]
+use lilygo_t7s3::SolarPanelPin;
+
+#[cfg(any(
+    feature = "lilygo-t7s3",
+    not(any(
+        feature = "lilygo-t7s3",
+        feature = "xiao-s3",
+        feature = "arduino-nano-esp32"
+    ))
+))]
+pub use lilygo_t7s3::{SLEEP_CONFIG, WAKE_BUTTON};
+
+#[cfg(feature = "xiao-s3")]
+pub use xiao_s3::{SLEEP_CONFIG, WAKE_BUTTON};
+
+#[cfg(feature = "arduino-nano-esp32")]
+pub use arduino_nano_esp32::{SLEEP_CONFIG, WAKE_BUTTON};
+
+#[cfg(all(feature = "solar", feature = "xiao-s3"))]
+use xiao_s3::SolarPanelPin;
+
+#[cfg(all(feature = "solar", feature = "arduino-nano-esp32"))]
+use arduino_nano_esp32::SolarPanelPin;
@@ -1,20 +1,46 @@
+#[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+use super::PulseInputs;
+#[cfg(any(feature = "pms5003", feature = "sds011"))]
+use super::UartPeripherals;
 use super::{
-    initialize_base_parts, BatteryPeripherals, I2cPeripherals, OnboardLedPeripherals,
-    SystemPeripherals, WifiPeripherals,
+    initialize_base_parts, AuxPeripherals, BatteryPeripherals, I2cPeripherals,
+    OnboardLedPeripherals, SleepConfig, SystemPeripherals, WakeButton, WifiPeripherals,
+    DEFAULT_BATTERY_CONFIG, DEFAULT_ISOLATED_PINS,
 };
+#[cfg(feature = "solar")]
+use super::{SolarPeripherals, SOLAR_PANEL_DIVIDER};
+#[cfg(feature = "solar")]
+use esp_idf_svc::hal::gpio::Gpio10;
 use esp_idf_svc::hal::{
     adc::ADC1,
-    gpio::{Gpio21, Gpio3, Gpio5, Gpio8},
+    gpio::{Gpio2, Gpio21, Gpio5, Gpio8},
     i2c::I2C1,
 };
 
+/// Deep sleep configuration of the board
+pub const SLEEP_CONFIG: SleepConfig = SleepConfig {
+    isolate: DEFAULT_ISOLATED_PINS,
+    hold: &[(21, true)], // onboard LED off
+    rtc_periph_off: true,
+};
+
+/// Wake-up button of the board (BOOT button)
+pub const WAKE_BUTTON: WakeButton = WakeButton {
+    pin: 0,
+    active_low: true,
+};
+
+/// Pin where the solar panel voltage divider is connected
+#[cfg(feature = "solar")]
+pub type SolarPanelPin = Gpio10<'static>;
+
 impl
     SystemPeripherals<
         I2C1<'static>,
         Gpio8<'static>,
         Gpio5<'static>,
         ADC1<'static>,
-        Gpio3<'static>,
+        Gpio2<'static>,
         Gpio21<'static>,
     >
 {
@@ -29,7 +55,9 @@ impl
             },
             battery: BatteryPeripherals {
                 adc: peripherals.adc1,
-                pin: peripherals.pins.gpio3,
+                pin: peripherals.pins.gpio2,
+                config: DEFAULT_BATTERY_CONFIG,
+                fuel_gauge: None,
             },
             onboard_led: OnboardLedPeripherals {
                 pin: peripherals.pins.gpio21,
@@ -40,6 +68,33 @@ impl
                 sys_loop,
             },
             temp_sensor: peripherals.temp_sensor,
+            aux: AuxPeripherals {
+                #[cfg(feature = "ds18b20")]
+                onewire: peripherals.pins.gpio4.into(),
+                #[cfg(any(feature = "pms5003", feature = "sds011"))]
+                particulate: UartPeripherals {
+                    uart: peripherals.uart1,
+                    tx: peripherals.pins.gpio6.degrade_output(),
+                    rx: peripherals.pins.gpio7.degrade_input(),
+                },
+            },
+            #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+            pulse: PulseInputs {
+                #[cfg(feature = "anemometer")]
+                anemometer: peripherals.pins.gpio1.degrade_input(),
+                #[cfg(feature = "rain-gauge")]
+                rain_gauge: peripherals.pins.gpio14.degrade_input(),
+            },
+            #[cfg(feature = "as3935")]
+            lightning_irq: peripherals.pins.gpio9.degrade_input(),
+            #[cfg(feature = "solar")]
+            solar: SolarPeripherals {
+                panel: peripherals.pins.gpio10,
+                chrg: peripherals.pins.gpio11.degrade_input(),
+                stdby: peripherals.pins.gpio12.degrade_input(),
+                enable: peripherals.pins.gpio13.degrade_output(),
+                divider: SOLAR_PANEL_DIVIDER,
+            },
         }
     }
 }
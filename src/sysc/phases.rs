@@ -0,0 +1,391 @@
+//! Timing and energy accounting of the phases of a wake cycle.
+//!
+//! ## How it works
+//! - Every phase of the cycle is timed, phases that happen in multiple parts (e.g. reading the sensors) are summed up.
+//! - The energy used by each phase is estimated from its duration and the current draw figures in `sys.rs`.
+//!   Time spent in light sleep while [waiting](super::pm::idle) is estimated separately.
+//! - At the end of the cycle, the durations are added to totals kept in RTC memory.
+//! - Every [`PHASE_REPORT_INTERVAL`] cycles, the average duration and energy per cycle are sent to the server
+//!   as a compact summary, and the totals are cleared.
+//!
+//! The totals survive deep sleep, but not a power loss. Cycles that end with an error are included as well.
+//! So are cycles cut short by the supervisor: their phases are kept in RTC memory, and added to the totals
+//! after the next boot.
+//!
+//! The running phase is tracked as well, so that the [supervisor](super::supervisor) can enforce its time budget.
+
+use super::{rtc::RtcCell, schedule};
+use crate::config::{
+    CURRENT_DRAW_CPU, CURRENT_DRAW_IDLE, CURRENT_DRAW_RADIO, CURRENT_DRAW_SCAN,
+    CURRENT_DRAW_SENSORS, PHASE_REPORT_INTERVAL,
+};
+use esp_idf_svc::sys::esp_timer_get_time;
+use pwmp_client::PwmpClient;
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Number of phases.
+const PHASES: usize = 11;
+/// Marks that no phase is running.
+const NO_PHASE: u8 = u8::MAX;
+
+/// Durations of the phases of the current cycle in microseconds, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static CURRENT: [AtomicU32; PHASES] = [const { AtomicU32::new(0) }; PHASES];
+
+/// Time spent idle (in light sleep) during the phases of the current cycle in microseconds, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static IDLE: [AtomicU32; PHASES] = [const { AtomicU32::new(0) }; PHASES];
+
+/// Whether the previous cycle was cut short, before its phases were added to the totals.
+#[link_section = ".rtc.data"]
+static CUT_SHORT: AtomicBool = AtomicBool::new(false);
+
+/// Index of the running phase.
+static ACTIVE: AtomicU8 = AtomicU8::new(NO_PHASE);
+
+/// Time since the reset when the running phase has started in milliseconds.
+static ACTIVE_SINCE_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Totals since the last report, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static TOTALS: RtcCell<Totals> = RtcCell::new(Totals::new());
+
+/// Phases of a wake cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// From the reset until `main` is started
+    Boot,
+
+    /// Initialization of the system peripherals, drivers and storage
+    PeripheralInit,
+
+    /// Starting the radio and scanning for networks
+    WifiScan,
+
+    /// Associating with the access point
+    WifiConnect,
+
+    /// Waiting for an IP address
+    Dhcp,
+
+    /// Connecting to the PWMP server and performing the handshake
+    Handshake,
+
+    /// Requesting the app configuration
+    Settings,
+
+    /// Reading the sensors
+    SensorRead,
+
+    /// Posting the measurements, additional channels and notifications
+    Post,
+
+    /// Checking for firmware updates
+    OtaCheck,
+
+    /// Downloading and installing a firmware update
+    Update,
+}
+
+/// A running phase, its duration is recorded when this is dropped.
+pub struct PhaseGuard {
+    /// The running phase.
+    phase: Phase,
+
+    /// When the phase has started.
+    start: Instant,
+}
+
+/// Time spent in a phase.
+#[derive(Clone, Copy, Default)]
+pub struct PhaseTime {
+    /// Duration of the phase
+    pub total: Duration,
+
+    /// Part of the duration spent idle (in light sleep)
+    pub idle: Duration,
+}
+
+/// Durations summed up over multiple cycles.
+#[derive(Clone, Copy)]
+struct Totals {
+    /// Total duration of each phase in microseconds.
+    us: [u64; PHASES],
+
+    /// Total idle time of each phase in microseconds.
+    idle_us: [u64; PHASES],
+
+    /// Number of cycles.
+    cycles: u32,
+}
+
+impl Phase {
+    /// All phases, in order.
+    pub const ALL: [Self; PHASES] = [
+        Self::Boot,
+        Self::PeripheralInit,
+        Self::WifiScan,
+        Self::WifiConnect,
+        Self::Dhcp,
+        Self::Handshake,
+        Self::Settings,
+        Self::SensorRead,
+        Self::Post,
+        Self::OtaCheck,
+        Self::Update,
+    ];
+
+    /// Short name used in the summary.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Boot => "boot",
+            Self::PeripheralInit => "init",
+            Self::WifiScan => "scan",
+            Self::WifiConnect => "connect",
+            Self::Dhcp => "dhcp",
+            Self::Handshake => "handshake",
+            Self::Settings => "settings",
+            Self::SensorRead => "sensors",
+            Self::Post => "post",
+            Self::OtaCheck => "ota",
+            Self::Update => "update",
+        }
+    }
+
+    /// Estimated current draw during the phase in mA.
+    pub const fn current_draw(self) -> f32 {
+        match self {
+            Self::Boot | Self::PeripheralInit => CURRENT_DRAW_CPU,
+            Self::WifiScan => CURRENT_DRAW_SCAN,
+            Self::SensorRead => CURRENT_DRAW_SENSORS,
+            Self::WifiConnect
+            | Self::Dhcp
+            | Self::Handshake
+            | Self::Settings
+            | Self::Post
+            | Self::OtaCheck
+            | Self::Update => CURRENT_DRAW_RADIO,
+        }
+    }
+
+    /// Index of the phase in [`Phase::ALL`].
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Add `duration` to the given phase of the current cycle.
+pub fn record(phase: Phase, duration: Duration) {
+    add(&CURRENT[phase.index()], duration);
+}
+
+/// Add `duration` to the idle time of the running phase, if any.
+pub fn record_idle(duration: Duration) {
+    if let Some((phase, _)) = active() {
+        add(&IDLE[phase.index()], duration);
+    }
+}
+
+/// Start the given phase.
+pub fn begin(phase: Phase) -> PhaseGuard {
+    #[allow(clippy::cast_possible_truncation)]
+    ACTIVE.store(phase.index() as u8, Ordering::Relaxed);
+    ACTIVE_SINCE_MS.store(uptime_ms(), Ordering::Relaxed);
+
+    PhaseGuard {
+        phase,
+        start: Instant::now(),
+    }
+}
+
+/// Run `f` and add the time it took to the given phase.
+pub fn measure<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let _guard = begin(phase);
+    f()
+}
+
+/// Returns the running phase and how long it has been running.
+pub fn active() -> Option<(Phase, Duration)> {
+    let phase = Phase::ALL.get(usize::from(ACTIVE.load(Ordering::Relaxed)))?;
+    let since_ms = ACTIVE_SINCE_MS.load(Ordering::Relaxed);
+
+    Some((
+        *phase,
+        Duration::from_millis(u64::from(uptime_ms().saturating_sub(since_ms))),
+    ))
+}
+
+/// Record the [`Phase::Boot`] phase, which ends when `main` is started.
+///
+/// This must be called at the very start of `main`. If the previous cycle was [cut short](cut_short()),
+/// it's added to the totals first.
+pub fn record_boot() {
+    if CUT_SHORT.swap(false, Ordering::Relaxed) {
+        finish();
+    } else {
+        clear();
+    }
+
+    record(Phase::Boot, Duration::from_millis(u64::from(uptime_ms())));
+}
+
+/// Finish the current cycle, log its phases and add them to the totals.
+pub fn finish() {
+    let times = Phase::ALL.map(|phase| PhaseTime {
+        total: Duration::from_micros(u64::from(CURRENT[phase.index()].load(Ordering::Relaxed))),
+        idle: Duration::from_micros(u64::from(IDLE[phase.index()].load(Ordering::Relaxed))),
+    });
+
+    log::debug!("Phases: {}", summary(&times));
+
+    update_totals(|totals| {
+        for (i, time) in times.iter().enumerate() {
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                totals.us[i] = totals.us[i].saturating_add(time.total.as_micros() as u64);
+                totals.idle_us[i] = totals.idle_us[i].saturating_add(time.idle.as_micros() as u64);
+            }
+        }
+
+        totals.cycles += 1;
+    });
+
+    clear();
+}
+
+/// Mark the current cycle as cut short, so that it's added to the totals after the next boot.
+///
+/// The running phase is recorded up to now. This only uses atomics, so it can be called from any task.
+pub fn cut_short() {
+    if let Some((phase, running)) = active() {
+        record(phase, running);
+    }
+
+    CUT_SHORT.store(true, Ordering::Relaxed);
+}
+
+/// Send the average phases per cycle to the server, if it's due in this cycle.
+///
+/// The current cycle is not included, since it's not finished yet.
+pub fn report_if_due(pws: &mut PwmpClient) {
+    let totals = totals();
+
+    if totals.cycles == 0 || !schedule::every(PHASE_REPORT_INTERVAL) {
+        return;
+    }
+
+    let cycles = u64::from(totals.cycles);
+    let averages = Phase::ALL.map(|phase| PhaseTime {
+        total: Duration::from_micros(totals.us[phase.index()] / cycles),
+        idle: Duration::from_micros(totals.idle_us[phase.index()] / cycles),
+    });
+
+    log::info!("Reporting phase summary");
+    match pws.send_notification(format!(
+        "Phases ({} cycles): {}",
+        totals.cycles,
+        summary(&averages)
+    )) {
+        Ok(()) => update_totals(|totals| *totals = Totals::new()),
+        Err(why) => log::warn!("Failed to report phase summary: {why}"),
+    }
+}
+
+/// Estimate the energy used by a phase in mAh.
+///
+/// The idle part of the phase is estimated using [`CURRENT_DRAW_IDLE`], the rest using `current_draw`.
+pub fn energy(time: PhaseTime, current_draw: f32) -> f32 {
+    let idle = time.idle.min(time.total);
+    let busy = time.total.saturating_sub(idle);
+
+    current_draw.mul_add(busy.as_secs_f32(), CURRENT_DRAW_IDLE * idle.as_secs_f32()) / 3600.0
+}
+
+/// Format the times of all phases (in the order of [`Phase::ALL`]) and the total energy.
+///
+/// Phases that took no time are left out, the idle time is only shown if there was any.
+pub fn summary(times: &[PhaseTime; PHASES]) -> String {
+    let mut text = String::new();
+    let mut total_time = Duration::ZERO;
+    let mut total_energy = 0.0;
+
+    for (phase, time) in Phase::ALL.iter().zip(times) {
+        if time.total.is_zero() {
+            continue;
+        }
+
+        let phase_energy = energy(*time, phase.current_draw());
+        total_time += time.total;
+        total_energy += phase_energy;
+
+        let _ = write!(text, "{}={}ms", phase.name(), time.total.as_millis());
+        if !time.idle.is_zero() {
+            let _ = write!(text, "({}ms idle)", time.idle.as_millis());
+        }
+        let _ = write!(text, "/{phase_energy:.04}mAh, ");
+    }
+
+    let _ = write!(
+        text,
+        "total={}ms/{:.04}mAh",
+        total_time.as_millis(),
+        total_energy
+    );
+
+    text
+}
+
+/// Reset the phases of the current cycle.
+fn clear() {
+    for slot in CURRENT.iter().chain(&IDLE) {
+        slot.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Add `duration` to a duration in microseconds.
+fn add(slot: &AtomicU32, duration: Duration) {
+    let us = u32::try_from(duration.as_micros()).unwrap_or(u32::MAX);
+
+    slot.store(
+        slot.load(Ordering::Relaxed).saturating_add(us),
+        Ordering::Relaxed,
+    );
+}
+
+/// Returns the time since the reset in milliseconds.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn uptime_ms() -> u32 {
+    // SAFETY: Calling a safe C function.
+    (unsafe { esp_timer_get_time() } / 1000) as u32
+}
+
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        record(self.phase, self.start.elapsed());
+        ACTIVE.store(NO_PHASE, Ordering::Relaxed);
+    }
+}
+
+impl Totals {
+    const fn new() -> Self {
+        Self {
+            us: [0; PHASES],
+            idle_us: [0; PHASES],
+            cycles: 0,
+        }
+    }
+}
+
+/// Get a copy of the totals.
+fn totals() -> Totals {
+    TOTALS.get()
+}
+
+/// Modify the totals.
+fn update_totals(f: impl FnOnce(&mut Totals)) {
+    TOTALS.update(f);
+}
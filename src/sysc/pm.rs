@@ -0,0 +1,102 @@
+//! Power management during blocking waits.
+//!
+//! ## How it works
+//! - The ESP-IDF power management is configured to scale the CPU frequency between [`MIN_FREQ_MHZ`] and
+//!   [`MAX_FREQ_MHZ`], and to enter light sleep automatically when all tasks are idle.
+//! - A lock keeps the CPU at full speed while the firmware is busy.
+//! - The lock is only released while [waiting](idle) for the radio driver or a sensor, so the CPU can slow down
+//!   or sleep until the next interrupt. With light sleep enabled, the time spent waiting is added to the idle time
+//!   of the running [phase](super::phases).
+//!
+//! Light sleep is not used while USB is connected, since it would interrupt the serial console.
+//! It can be disabled completely with the `no-light-sleep` feature, frequency scaling is still used then.
+
+use super::{phases, usbctl, OsResult};
+use crate::re_esp;
+use esp_idf_svc::sys::{
+    esp, esp_pm_config_t, esp_pm_configure, esp_pm_lock_acquire, esp_pm_lock_create,
+    esp_pm_lock_handle_t, esp_pm_lock_release, esp_pm_lock_type_t_ESP_PM_CPU_FREQ_MAX,
+};
+use std::{
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+    time::Instant,
+};
+
+/// CPU frequency while the firmware is busy.
+const MAX_FREQ_MHZ: i32 = 240;
+/// Lowest CPU frequency while waiting.
+const MIN_FREQ_MHZ: i32 = 40;
+
+/// Lock that keeps the CPU at full speed, null if power management is not configured.
+static BUSY_LOCK: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Whether light sleep is enabled.
+static LIGHT_SLEEP: AtomicBool = AtomicBool::new(false);
+
+/// Configure power management and keep the CPU at full speed until the next [wait](idle).
+///
+/// # Errors
+/// Returns an error if power management is not supported by the ESP-IDF configuration,
+/// or if the lock cannot be created.
+pub fn configure() -> OsResult<()> {
+    let light_sleep = !cfg!(feature = "no-light-sleep") && !usbctl::is_connected();
+    let config = esp_pm_config_t {
+        max_freq_mhz: MAX_FREQ_MHZ,
+        min_freq_mhz: MIN_FREQ_MHZ,
+        light_sleep_enable: light_sleep,
+    };
+    let mut lock: esp_pm_lock_handle_t = ptr::null_mut();
+
+    // SAFETY: The lock is created and acquired before the configuration is applied,
+    //         the pointers are valid for the duration of the calls.
+    unsafe {
+        re_esp!(
+            esp!(esp_pm_lock_create(
+                esp_pm_lock_type_t_ESP_PM_CPU_FREQ_MAX,
+                0,
+                c"busy".as_ptr(),
+                &raw mut lock
+            )),
+            PmInit
+        )?;
+        re_esp!(esp!(esp_pm_lock_acquire(lock)), PmInit)?;
+        re_esp!(
+            esp!(esp_pm_configure(ptr::from_ref(&config).cast())),
+            PmInit
+        )?;
+    }
+
+    BUSY_LOCK.store(lock, Ordering::Relaxed);
+    LIGHT_SLEEP.store(light_sleep, Ordering::Relaxed);
+    log::debug!("Power management configured, light sleep: {light_sleep}");
+
+    Ok(())
+}
+
+/// Run `f`, which blocks while waiting for an event, without keeping the CPU at full speed.
+///
+/// If light sleep is enabled, the time spent in `f` is recorded as idle time of the running phase.
+pub fn idle<T>(f: impl FnOnce() -> T) -> T {
+    let lock = BUSY_LOCK.load(Ordering::Relaxed);
+
+    if lock.is_null() {
+        return f();
+    }
+
+    // SAFETY: The lock is valid and acquired.
+    unsafe { esp_pm_lock_release(lock) };
+    let start = Instant::now();
+
+    let result = f();
+
+    let waited = start.elapsed();
+    // SAFETY: The lock is valid and released above.
+    unsafe { esp_pm_lock_acquire(lock) };
+
+    if LIGHT_SLEEP.load(Ordering::Relaxed) {
+        phases::record_idle(waited);
+    }
+
+    result
+}
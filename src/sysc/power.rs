@@ -1,11 +1,31 @@
+#[cfg(feature = "solar")]
+use super::periph::CHARGER_ENABLE_PIN;
+use super::{
+    periph::{SLEEP_CONFIG, WAKE_BUTTON},
+    ReportableError,
+};
 pub use esp_idf_svc::hal::reset::ResetReason;
 use esp_idf_svc::{
     hal::reset::restart,
-    sys::{esp_deep_sleep, esp_reset_reason},
+    sys::{
+        esp, esp_deep_sleep, esp_reset_reason, esp_sleep_get_ext1_wakeup_status,
+        esp_sleep_get_wakeup_cause, esp_sleep_pd_config,
+        esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH, esp_sleep_pd_option_t_ESP_PD_OPTION_OFF,
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0, esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1,
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER, esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED,
+        gpio_deep_sleep_hold_en, gpio_hold_dis, gpio_hold_en, gpio_mode_t_GPIO_MODE_OUTPUT,
+        gpio_set_direction, gpio_set_level, rtc_gpio_isolate,
+    },
 };
 use std::time::Duration;
 
 const INFINITE_SLEEP_TIME: Duration = Duration::from_secs(2_629_746); /* 1 month */
+/// Whether a wake-up source needs the RTC peripherals powered during deep sleep.
+const WAKE_NEEDS_RTC_PERIPH: bool = cfg!(any(
+    feature = "anemometer",
+    feature = "rain-gauge",
+    feature = "as3935"
+));
 
 /// Puts the node into sleep mode, while automatically selecting the proper
 /// sleep type (*deep*/*fake*) depending on whether the node is powered trough USB
@@ -24,11 +44,66 @@ fn deep_sleep(time: Option<Duration>) -> ! {
     let us = u64::try_from(time.unwrap_or(INFINITE_SLEEP_TIME).as_micros())
         .expect("Deep sleep duration is too long");
 
+    prepare_deep_sleep();
+
     unsafe {
         esp_deep_sleep(us);
     }
 }
 
+/// Configure the pins and power domains for deep sleep, as given by the board profile.
+///
+/// The RTC memory is left in automatic mode, since it keeps the state between wake-ups.
+fn prepare_deep_sleep() {
+    // pins, whose level is only known at runtime
+    let dynamic_hold: &[(i32, bool)] = &[
+        // the charger must stay disabled outside of the safe temperature range
+        #[cfg(feature = "solar")]
+        (CHARGER_ENABLE_PIN, super::thermal::charging_allowed()),
+    ];
+
+    // SAFETY: Calling safe C functions, the pin drivers have been dropped by now.
+    unsafe {
+        for &(pin, high) in SLEEP_CONFIG.hold.iter().chain(dynamic_hold) {
+            esp!(gpio_set_direction(pin, gpio_mode_t_GPIO_MODE_OUTPUT))
+                .and_then(|()| esp!(gpio_set_level(pin, u32::from(high))))
+                .and_then(|()| esp!(gpio_hold_en(pin)))
+                .report("Failed to hold pin during deep sleep");
+        }
+
+        // digital pads (e.g. GPIO48) only keep the hold in deep sleep if enabled globally
+        if !SLEEP_CONFIG.hold.is_empty() || !dynamic_hold.is_empty() {
+            gpio_deep_sleep_hold_en();
+        }
+
+        for &pin in SLEEP_CONFIG.isolate {
+            esp!(rtc_gpio_isolate(pin)).report("Failed to isolate pin during deep sleep");
+        }
+
+        if SLEEP_CONFIG.rtc_periph_off && !WAKE_NEEDS_RTC_PERIPH {
+            esp!(esp_sleep_pd_config(
+                esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH,
+                esp_sleep_pd_option_t_ESP_PD_OPTION_OFF
+            ))
+            .report("Failed to power down RTC peripherals");
+        }
+    }
+}
+
+/// Release the pins held by the previous deep sleep, so that they can be used again.
+///
+/// This must be called before the peripherals are initialized.
+pub fn release_sleep_holds() {
+    for pin in SLEEP_CONFIG
+        .isolate
+        .iter()
+        .chain(SLEEP_CONFIG.hold.iter().map(|(pin, _)| pin))
+    {
+        // SAFETY: Calling a safe C function.
+        esp!(unsafe { gpio_hold_dis(*pin) }).report("Failed to release pin hold");
+    }
+}
+
 /// Simulates a sleep using [`std::thread::sleep`] for the specified amount of time, or
 /// indefinetly, if the duration is [None] and then performs a software reset.
 ///
@@ -45,6 +120,47 @@ pub fn get_reset_reason() -> ResetReason {
     unsafe { esp_reset_reason() }.into()
 }
 
+/// Source of the last wake-up from deep sleep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeupCause {
+    /// The node was not woken up from deep sleep (e.g. power-on or reset).
+    None,
+
+    /// The sleep timer has expired.
+    Timer,
+
+    /// A single RTC GPIO pin has reached the configured level.
+    Ext0,
+
+    /// One of multiple RTC GPIO pins has reached the configured level.
+    Ext1,
+
+    /// The wake-up button has been pressed.
+    Button,
+
+    /// Any other wake-up source.
+    Other,
+}
+
+/// Returns the cause of the last wake-up from deep sleep.
+#[allow(non_upper_case_globals)]
+pub fn get_wakeup_cause() -> WakeupCause {
+    // SAFETY: Calling a safe C function.
+    match unsafe { esp_sleep_get_wakeup_cause() } {
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_UNDEFINED => WakeupCause::None,
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_TIMER => WakeupCause::Timer,
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT0 => WakeupCause::Ext0,
+        // SAFETY: Calling a safe C function.
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1
+            if unsafe { esp_sleep_get_ext1_wakeup_status() } & (1 << WAKE_BUTTON.pin) != 0 =>
+        {
+            WakeupCause::Button
+        }
+        esp_sleep_source_t_ESP_SLEEP_WAKEUP_EXT1 => WakeupCause::Ext1,
+        _ => WakeupCause::Other,
+    }
+}
+
 /// A trait for extending [`ResetReason`].
 pub trait ResetReasonExt {
     /// Returns whether the reset reason is abnormal (caused by a crash/error).
@@ -0,0 +1,304 @@
+//! Pulse counting for reed-switch anemometers and tipping-bucket rain gauges.
+//!
+//! ## How it works
+//! Keeping the MCU awake to count pulses would drain the battery, so they're counted while it sleeps:
+//! - Every input is an RTC GPIO, which wakes the MCU up from deep sleep when pulled low (*ext1* wake-up).
+//! - After such a wake-up, the pulse is recorded in RTC memory and the MCU is put back to sleep right away,
+//!   until the originally scheduled wake-up. This is normally done by the [wake stub](super::wake_stub) without a full boot,
+//!   otherwise by the firmware (see [`WakeSources::resume_sleep()`](super::wake::WakeSources::resume_sleep)).
+//! - On a scheduled wake-up, the counts are converted using the calibration constants from `sys.rs` and reported.
+//!
+//! The counts survive deep sleep, but not a power loss. Pulses are not counted while the node is awake,
+//! or while it's powered over USB, since deep sleep is not used then.
+//!
+//! The debouncing and conversion logic is in [`pwos_logic::pulse`], which does not depend on the hardware.
+
+#[cfg(feature = "anemometer")]
+use crate::config::{ANEMOMETER_DEBOUNCE, ANEMOMETER_FACTOR};
+#[cfg(feature = "rain-gauge")]
+use crate::config::{RAIN_GAUGE_DEBOUNCE, RAIN_GAUGE_MM_PER_TIP};
+use crate::{
+    re_esp,
+    sysc::{
+        channels::Channels,
+        periph::PulseInputs,
+        power::{get_wakeup_cause, WakeupCause},
+        rtc::RtcCell,
+        schedule::rtc_us,
+        wake_stub, OsResult,
+    },
+};
+use esp_idf_svc::{
+    hal::gpio::{AnyInputPin, Pin},
+    sys::{
+        esp, esp_sleep_enable_ext1_wakeup_io, esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_LOW,
+        esp_sleep_get_ext1_wakeup_status, esp_sleep_pd_config,
+        esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH, esp_sleep_pd_option_t_ESP_PD_OPTION_ON,
+        rtc_gpio_get_level, rtc_gpio_init, rtc_gpio_mode_t_RTC_GPIO_MODE_INPUT_ONLY,
+        rtc_gpio_pulldown_dis, rtc_gpio_pullup_en, rtc_gpio_set_direction,
+    },
+};
+#[cfg(feature = "rain-gauge")]
+use pwos_logic::pulse::rainfall;
+use pwos_logic::pulse::Counter;
+#[cfg(feature = "anemometer")]
+use pwos_logic::pulse::{gust, period, wind_speed};
+use std::{thread::sleep, time::Duration};
+
+/// Number of supported inputs.
+pub const INPUTS: usize = 2;
+/// How long to wait for a switch to open again, after it has woken the MCU up.
+const RELEASE_TIMEOUT: Duration = Duration::from_millis(50);
+/// Longest plausible counting period. Counts are kept if a report fails, but not for longer than this.
+#[cfg(feature = "anemometer")]
+const MAX_PERIOD: Duration = Duration::from_hours(24);
+
+/// State of all inputs, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static STATE: RtcCell<State> = RtcCell::new(State::new());
+
+/// Pulse inputs.
+#[derive(Clone, Copy)]
+pub enum Input {
+    /// Reed-switch anemometer
+    #[cfg(feature = "anemometer")]
+    Anemometer,
+
+    /// Tipping-bucket rain gauge
+    #[cfg(feature = "rain-gauge")]
+    RainGauge,
+}
+
+/// Pulse counter handle, that owns the input pins.
+pub struct PulseCounter(Vec<(Input, AnyInputPin<'static>)>);
+
+/// Pulse counting state.
+#[derive(Clone, Copy)]
+struct State {
+    /// Counters of all inputs, indexed by [`Input::slot()`].
+    counters: [Counter; INPUTS],
+
+    /// RTC time when the current counting period has started in microseconds.
+    period_start_us: Option<u64>,
+}
+
+impl PulseCounter {
+    /// Set up the input pins as RTC GPIOs with pull-up resistors.
+    ///
+    /// # Errors
+    /// Returns an error if any of the pins cannot be configured.
+    pub fn new(inputs: PulseInputs) -> OsResult<Self> {
+        #[allow(unused_mut)]
+        let mut pins = Vec::with_capacity(INPUTS);
+
+        #[cfg(feature = "anemometer")]
+        pins.push((Input::Anemometer, inputs.anemometer));
+
+        #[cfg(feature = "rain-gauge")]
+        pins.push((Input::RainGauge, inputs.rain_gauge));
+
+        for (_, pin) in &pins {
+            // SAFETY: The pins are owned by this driver.
+            unsafe {
+                re_esp!(esp!(rtc_gpio_init(pin.pin())), GpioInit)?;
+                re_esp!(
+                    esp!(rtc_gpio_set_direction(
+                        pin.pin(),
+                        rtc_gpio_mode_t_RTC_GPIO_MODE_INPUT_ONLY
+                    )),
+                    GpioInit
+                )?;
+                re_esp!(esp!(rtc_gpio_pullup_en(pin.pin())), GpioInit)?;
+                re_esp!(esp!(rtc_gpio_pulldown_dis(pin.pin())), GpioInit)?;
+            }
+        }
+
+        STATE.update(|state| {
+            state.period_start_us.get_or_insert_with(rtc_us);
+        });
+
+        Ok(Self(pins))
+    }
+
+    /// Record the pulses that have woken the MCU up.
+    ///
+    /// Returns whether the MCU was woken up by a pulse.
+    pub fn handle_wakeup(&self) -> bool {
+        // the button may have been pressed at the same time
+        if !matches!(get_wakeup_cause(), WakeupCause::Ext1 | WakeupCause::Button) {
+            return false;
+        }
+
+        // SAFETY: Calling a safe C function.
+        let status = unsafe { esp_sleep_get_ext1_wakeup_status() };
+        let now = rtc_us();
+
+        let mut pulse = false;
+
+        for (input, pin) in &self.0 {
+            if status & (1 << pin.pin()) != 0 {
+                STATE.update(|state| state.counters[input.slot()].record(now, input.debounce()));
+                pulse = true;
+            }
+        }
+
+        if pulse {
+            self.wait_for_release();
+        }
+
+        pulse
+    }
+
+    /// Let the wake stub handle the inputs during the next deep sleep.
+    pub fn configure_stub(&self) {
+        for (input, pin) in &self.0 {
+            wake_stub::configure(input.slot(), pin.pin(), input.debounce());
+        }
+    }
+
+    /// Enable waking up on pulses during the next deep sleep.
+    ///
+    /// Inputs that are being held low (e.g. the anemometer stopped with the magnet at the switch) are not enabled,
+    /// as they would wake the MCU up immediately.
+    ///
+    /// # Errors
+    /// Returns an error if the wake-up source cannot be configured.
+    pub fn arm(&self) -> OsResult<()> {
+        let mask = self
+            .0
+            .iter()
+            .filter(|(_, pin)| Self::released(pin))
+            .fold(0u64, |mask, (_, pin)| mask | (1 << pin.pin()));
+
+        if mask == 0 {
+            return Ok(());
+        }
+
+        // SAFETY: Calling safe C functions.
+        unsafe {
+            // the pull-up resistors must stay powered during deep sleep
+            re_esp!(
+                esp!(esp_sleep_pd_config(
+                    esp_sleep_pd_domain_t_ESP_PD_DOMAIN_RTC_PERIPH,
+                    esp_sleep_pd_option_t_ESP_PD_OPTION_ON
+                )),
+                GpioInit
+            )?;
+            re_esp!(
+                esp!(esp_sleep_enable_ext1_wakeup_io(
+                    mask,
+                    esp_sleep_ext1_wakeup_mode_t_ESP_EXT1_WAKEUP_ANY_LOW
+                )),
+                GpioInit
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait until all switches are open, or until [`RELEASE_TIMEOUT`] expires.
+    fn wait_for_release(&self) {
+        let start = std::time::Instant::now();
+
+        while start.elapsed() < RELEASE_TIMEOUT
+            && !self.0.iter().all(|(_, pin)| Self::released(pin))
+        {
+            sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Returns whether the switch connected to the pin is open.
+    fn released(pin: &AnyInputPin<'static>) -> bool {
+        // SAFETY: The pin is owned by this driver.
+        unsafe { rtc_gpio_get_level(pin.pin()) != 0 }
+    }
+}
+
+/// Convert the pulses counted during the current period and add them to `channels`.
+///
+/// The counts are kept until [`reset()`] is called.
+pub fn collect(channels: &mut Channels) {
+    let state = STATE.get();
+
+    #[cfg(feature = "anemometer")]
+    {
+        let counter = with_stub_pulses(state.counters[Input::Anemometer.slot()], Input::Anemometer);
+
+        match period(state.period_start_us, rtc_us(), MAX_PERIOD) {
+            Some(period) => channels.push(
+                "wind_speed",
+                wind_speed(counter.count, period, ANEMOMETER_FACTOR),
+                "km/h",
+            ),
+            None => log::warn!("Counting period is not plausible, not reporting wind speed"),
+        }
+        channels.push(
+            "wind_gust",
+            counter
+                .min_interval_us
+                .map_or(0.0, |us| gust(Duration::from_micros(us), ANEMOMETER_FACTOR)),
+            "km/h",
+        );
+    }
+
+    #[cfg(feature = "rain-gauge")]
+    channels.push(
+        "rainfall",
+        rainfall(
+            with_stub_pulses(state.counters[Input::RainGauge.slot()], Input::RainGauge).count,
+            RAIN_GAUGE_MM_PER_TIP,
+        ),
+        "mm",
+    );
+}
+
+/// Clear all counters and start a new counting period.
+pub fn reset() {
+    STATE.update(|state| {
+        *state = State {
+            period_start_us: Some(rtc_us()),
+            ..State::new()
+        };
+    });
+    wake_stub::reset();
+}
+
+/// Add the pulses recorded by the wake stub on the given `input` to `counter`.
+fn with_stub_pulses(counter: Counter, input: Input) -> Counter {
+    let (count, min_interval) = wake_stub::pulses(input.slot());
+    #[allow(clippy::cast_possible_truncation)]
+    let min_interval_us = min_interval.map(|interval| interval.as_micros() as u64);
+
+    counter.merge(count, min_interval_us)
+}
+
+impl Input {
+    /// Index of the input's counter in the RTC state.
+    const fn slot(self) -> usize {
+        match self {
+            #[cfg(feature = "anemometer")]
+            Self::Anemometer => 0,
+            #[cfg(feature = "rain-gauge")]
+            Self::RainGauge => 1,
+        }
+    }
+
+    /// Minimum time between two pulses.
+    const fn debounce(self) -> Duration {
+        match self {
+            #[cfg(feature = "anemometer")]
+            Self::Anemometer => ANEMOMETER_DEBOUNCE,
+            #[cfg(feature = "rain-gauge")]
+            Self::RainGauge => RAIN_GAUGE_DEBOUNCE,
+        }
+    }
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            counters: [Counter::new(); INPUTS],
+            period_start_us: None,
+        }
+    }
+}
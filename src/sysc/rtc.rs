@@ -0,0 +1,41 @@
+//! State kept in RTC memory, that survives deep sleep.
+
+use std::cell::UnsafeCell;
+
+/// A value kept in RTC memory.
+///
+/// Statics of this type must be placed in the `.rtc.data` section, otherwise they're reset on every wake-up.
+/// The value survives deep sleep, but not a power loss. It must only be accessed by the main task,
+/// state shared with other tasks (like the [supervisor](super::supervisor)) uses atomics instead.
+pub struct RtcCell<T>(UnsafeCell<T>);
+
+// SAFETY: RTC state is only accessed by the main task (and by the wake stub, before the OS is started).
+unsafe impl<T> Sync for RtcCell<T> {}
+
+impl<T: Copy> RtcCell<T> {
+    /// Create a cell with the value used after a power loss.
+    pub const fn new(value: T) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+
+    /// Get a copy of the value.
+    pub fn get(&self) -> T {
+        // SAFETY: See the `Sync` implementation, no reference to the value is held across calls.
+        unsafe { *self.0.get() }
+    }
+
+    /// Modify the value.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        // SAFETY: See the `Sync` implementation, no reference to the value is held across calls.
+        unsafe { f(&mut *self.0.get()) }
+    }
+
+    /// Get a raw pointer to the value.
+    ///
+    /// This is always inlined, so that it can be used by the wake stub.
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    pub const fn as_ptr(&self) -> *mut T {
+        self.0.get()
+    }
+}
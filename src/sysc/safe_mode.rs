@@ -0,0 +1,208 @@
+//! Crash-loop detection and safe mode.
+//!
+//! ## How it works
+//! - Abnormal resets (panics, watchdog resets, brownouts, ...) are counted in the boot history, which is cleared
+//!   once a wake cycle finishes without crashing.
+//! - After [`SAFE_MODE_RESETS`] abnormal resets in a row, the node enters safe mode. Firmware updates and
+//!   the optional sensors are skipped, only the strongest known network is tried, and the sleep time is multiplied
+//!   by [`SAFE_MODE_SLEEP_MULTIPLIER`].
+//! - Safe mode is left after [`SAFE_MODE_CLEAN_CYCLES`] successful wake cycles in a row. Another abnormal reset
+//!   starts the count again.
+//! - Entering and leaving safe mode is reported to the server.
+//!
+//! The boot history is kept in NVS, since RTC memory is reinitialized after an abnormal reset.
+//! It's only written when it changes, so a healthy node does not wear out the flash.
+
+use super::{
+    nvs::NonVolatileStorage,
+    power::{ResetReason, ResetReasonExt},
+    ReportableError,
+};
+use crate::config::{SAFE_MODE_CLEAN_CYCLES, SAFE_MODE_RESETS, SAFE_MODE_SLEEP_MULTIPLIER};
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+/// Boot history of the current run.
+static HISTORY: AtomicU32 = AtomicU32::new(BootHistory::new().to_bits());
+
+/// Recent resets and the state of safe mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootHistory {
+    /// Number of abnormal resets in a row
+    pub resets: u8,
+
+    /// Successful wake cycles left until safe mode is left, `0` if not in safe mode
+    pub safe_cycles: u8,
+
+    /// Whether entering or leaving safe mode was reported to the server
+    pub reported: bool,
+}
+
+impl BootHistory {
+    /// Empty history, not in safe mode.
+    pub const fn new() -> Self {
+        Self {
+            resets: 0,
+            safe_cycles: 0,
+            reported: true,
+        }
+    }
+
+    /// Returns whether the node is in safe mode.
+    pub const fn safe_mode(self) -> bool {
+        self.safe_cycles != 0
+    }
+
+    /// Record a boot, `abnormal` if it was caused by an abnormal reset.
+    ///
+    /// Safe mode is entered after `threshold` abnormal resets in a row, and lasts for `clean_cycles` successful cycles.
+    #[must_use]
+    pub const fn boot(mut self, abnormal: bool, threshold: u8, clean_cycles: u8) -> Self {
+        if !abnormal {
+            return self;
+        }
+
+        self.resets = self.resets.saturating_add(1);
+
+        if self.safe_mode() {
+            self.safe_cycles = clean_cycles;
+        } else if self.resets >= threshold && clean_cycles != 0 {
+            self.safe_cycles = clean_cycles;
+            self.reported = false;
+        }
+
+        self
+    }
+
+    /// Record the end of a wake cycle without a crash, `successful` if it finished without an error.
+    #[must_use]
+    pub const fn finish(mut self, successful: bool) -> Self {
+        self.resets = 0;
+
+        if successful && self.safe_mode() {
+            self.safe_cycles -= 1;
+
+            if !self.safe_mode() {
+                self.reported = false;
+            }
+        }
+
+        self
+    }
+
+    /// Pack the history into a single integer for storage.
+    pub const fn to_bits(self) -> u32 {
+        (self.resets as u32) << 16 | (self.safe_cycles as u32) << 8 | self.reported as u32
+    }
+
+    /// Unpack a history packed using [`to_bits()`](Self::to_bits).
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_bits(bits: u32) -> Self {
+        Self {
+            resets: (bits >> 16) as u8,
+            safe_cycles: (bits >> 8) as u8,
+            reported: bits & 1 != 0,
+        }
+    }
+}
+
+/// Load the boot history and record the boot caused by `reason`.
+///
+/// If the previous cycle was `cut_short` by the supervisor, it's recorded as a failed cycle first.
+pub fn check(nvs: &NonVolatileStorage, reason: ResetReason, cut_short: bool) {
+    let history = nvs
+        .get_boot_history()
+        .inspect_err(|why| log::warn!("Failed to read boot history: {why}"))
+        .ok()
+        .flatten()
+        .unwrap_or(BootHistory::new());
+    HISTORY.store(history.to_bits(), Ordering::Relaxed);
+
+    update(nvs, |history| {
+        let history = if cut_short {
+            history.finish(false)
+        } else {
+            history
+        };
+
+        history.boot(
+            reason.is_abnormal(),
+            SAFE_MODE_RESETS,
+            SAFE_MODE_CLEAN_CYCLES,
+        )
+    });
+
+    let history = current();
+    if history.safe_mode() {
+        log::warn!(
+            "Running in safe mode, {} successful cycles left",
+            history.safe_cycles
+        );
+    }
+}
+
+/// Returns whether the node is in safe mode.
+pub fn is_active() -> bool {
+    current().safe_mode()
+}
+
+/// Returns the sleep time adjusted for safe mode.
+pub fn sleep_time(sleep_time: Duration) -> Duration {
+    if is_active() {
+        sleep_time * SAFE_MODE_SLEEP_MULTIPLIER
+    } else {
+        sleep_time
+    }
+}
+
+/// Record the end of the wake cycle, `successful` if it finished without an error.
+pub fn finish(nvs: &NonVolatileStorage, successful: bool) {
+    let was_active = is_active();
+    update(nvs, |history| history.finish(successful));
+
+    if was_active && !is_active() {
+        log::info!("Leaving safe mode");
+    }
+}
+
+/// Returns the notification about entering or leaving safe mode, if it was not sent yet.
+pub fn pending_notification() -> Option<String> {
+    let history = current();
+
+    if history.reported {
+        None
+    } else if history.safe_mode() {
+        Some(format!(
+            "Entered safe mode after {SAFE_MODE_RESETS} abnormal resets in a row"
+        ))
+    } else {
+        Some("Left safe mode".to_string())
+    }
+}
+
+/// Mark the pending notification as sent.
+pub fn notification_sent(nvs: &NonVolatileStorage) {
+    update(nvs, |history| BootHistory {
+        reported: true,
+        ..history
+    });
+}
+
+/// Returns the boot history of the current run.
+fn current() -> BootHistory {
+    BootHistory::from_bits(HISTORY.load(Ordering::Relaxed))
+}
+
+/// Modify the boot history, and write it to NVS if it has changed.
+fn update(nvs: &NonVolatileStorage, f: impl FnOnce(BootHistory) -> BootHistory) {
+    let history = current();
+    let updated = f(history);
+
+    if updated != history {
+        HISTORY.store(updated.to_bits(), Ordering::Relaxed);
+        nvs.store_boot_history(updated)
+            .report("Failed to store boot history");
+    }
+}
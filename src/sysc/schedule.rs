@@ -0,0 +1,114 @@
+//! Scheduling of tasks across wake cycles.
+//!
+//! Some tasks are too expensive to be done on every wake-up. The number of wake-ups is kept
+//! in RTC memory, so it survives deep sleep, but not a power loss.
+//!
+//! The time of the next regular wake-up is kept as well, so that the node can go back to sleep
+//! if it was woken up early by an event (e.g. a pulse or an interrupt from a sensor).
+
+use super::rtc::RtcCell;
+use esp_idf_svc::sys::esp_rtc_get_time_us;
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// If the regular wake-up is closer than this, the node should not go back to sleep.
+#[cfg(any(feature = "anemometer", feature = "rain-gauge", feature = "as3935"))]
+const MIN_RESUME_SLEEP_TIME: Duration = Duration::from_secs(1);
+
+/// Number of wake-ups since the last power loss.
+#[link_section = ".rtc.data"]
+static WAKE_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// RTC time of the next regular wake-up in microseconds.
+#[link_section = ".rtc.data"]
+static NEXT_WAKE_US: RtcCell<Option<u64>> = RtcCell::new(None);
+
+/// Tracks the warm-up of a peripheral, while the firmware is doing other work.
+#[cfg_attr(not(any(feature = "pms5003", feature = "sds011")), allow(dead_code))]
+pub struct Warmup {
+    /// When the warm-up has started.
+    start: Instant,
+
+    /// How long the warm-up takes.
+    duration: Duration,
+}
+
+/// Start a new wake cycle.
+///
+/// This must be called exactly once after a regular wake-up. Returns the number of the new cycle, starting at `0`.
+pub fn advance() -> u32 {
+    WAKE_COUNT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Returns the number of the current wake cycle.
+pub fn cycle() -> u32 {
+    WAKE_COUNT.load(Ordering::Relaxed).saturating_sub(1)
+}
+
+/// Returns whether a task that runs every `interval` wake cycles should run in the current one.
+///
+/// Tasks always run in the first cycle after a power loss. An interval of `0` disables the task.
+pub fn every(interval: u32) -> bool {
+    interval != 0 && cycle().is_multiple_of(interval)
+}
+
+/// Remember when the next regular wake-up will happen, if the node goes to sleep for `sleep_time` now.
+pub fn set_next_wake(sleep_time: Duration) {
+    let sleep_us = u64::try_from(sleep_time.as_micros()).unwrap_or(u64::MAX);
+
+    NEXT_WAKE_US.update(|next| *next = Some(rtc_us().saturating_add(sleep_us)));
+}
+
+/// Returns the remaining time until the regular wake-up, if the node has been woken up early.
+///
+/// Returns `None` if the regular wake-up is due, or if it's unknown (e.g. after a power loss).
+#[cfg(any(feature = "anemometer", feature = "rain-gauge", feature = "as3935"))]
+pub fn remaining_sleep() -> Option<Duration> {
+    let next_wake_us = NEXT_WAKE_US.get()?;
+    let remaining = Duration::from_micros(next_wake_us.saturating_sub(rtc_us()));
+
+    (remaining >= MIN_RESUME_SLEEP_TIME).then_some(remaining)
+}
+
+/// Returns the current system time in microseconds.
+///
+/// Unlike [`Instant`], the system time keeps running during deep sleep.
+#[allow(clippy::cast_possible_truncation)]
+pub fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// Returns the time since the last power loss in microseconds.
+///
+/// Unlike [`now_us()`], this keeps counting steadily when the time is synchronized, so it's used to measure intervals.
+pub fn rtc_us() -> u64 {
+    // SAFETY: Reading the RTC timer has no side effects.
+    unsafe { esp_rtc_get_time_us() }
+}
+
+#[cfg_attr(not(any(feature = "pms5003", feature = "sds011")), allow(dead_code))]
+impl Warmup {
+    /// Start tracking a warm-up, that takes `duration`.
+    pub fn start(duration: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            duration,
+        }
+    }
+
+    /// Block until the warm-up is finished.
+    pub fn wait(&self) {
+        let remaining = self.duration.saturating_sub(self.start.elapsed());
+
+        if !remaining.is_zero() {
+            log::debug!("Waiting {remaining:.02?} for warm-up to finish");
+            sleep(remaining);
+        }
+    }
+}
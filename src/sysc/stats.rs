@@ -0,0 +1,280 @@
+//! Reliability statistics.
+//!
+//! ## How it works
+//! - Boots, wake cycles (successful, or failed by [category](ErrorCategory)), `WiFi` connection failures,
+//!   firmware update attempts and the time spent awake are counted in RTC memory.
+//! - The statistics are checkpointed to NVS every [`STATS_CHECKPOINT_INTERVAL`] wake cycles, and restored from
+//!   the checkpoint when RTC memory is lost (power loss, crash or brownout). Counts since the last checkpoint
+//!   are lost then, except for the boot itself, which causes an immediate checkpoint.
+//! - Once the time is synchronized (see [`clock`](super::clock)), the time of the last power-on is derived
+//!   from the RTC clock, which keeps running since the power-on.
+//! - The statistics are reported to the server every [`STATS_REPORT_INTERVAL`] wake cycles, so they can be
+//!   compared across nodes. They are not cleared by the report, only by the `stats reset` console command.
+//!   Resetting them remotely is deferred until PWMP has a way for the server to send commands to a node.
+
+use super::{
+    clock,
+    nvs::NonVolatileStorage,
+    power::ResetReason,
+    rtc::RtcCell,
+    schedule::{self, now_us, rtc_us},
+    ErrorCategory, OsError, ReportableError,
+};
+use crate::config::{STATS_CHECKPOINT_INTERVAL, STATS_REPORT_INTERVAL};
+use pwmp_client::PwmpClient;
+use std::{fmt::Write, time::Duration};
+
+/// Number of error categories.
+const CATEGORIES: usize = ErrorCategory::ALL.len();
+
+/// Statistics of the current run, kept in RTC memory. `None` if RTC memory was lost.
+#[link_section = ".rtc.data"]
+static STATS: RtcCell<Option<Stats>> = RtcCell::new(None);
+
+/// Counters collected across wake cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of boots, including wake-ups from deep sleep
+    pub boots: u32,
+
+    /// Number of boots caused by a power-on
+    pub power_ons: u32,
+
+    /// Number of wake cycles that finished without an error
+    pub successful: u32,
+
+    /// Number of failed wake cycles, by [`ErrorCategory`]
+    pub failed: [u32; CATEGORIES],
+
+    /// Number of failed `WiFi` connection attempts
+    pub wifi_failures: u32,
+
+    /// Number of firmware update attempts
+    pub ota_attempts: u32,
+
+    /// Total time spent awake in milliseconds
+    pub awake_ms: u64,
+
+    /// UNIX time of the last power-on in seconds, `0` if unknown
+    pub last_power_on: u64,
+}
+
+impl Stats {
+    /// Size of the [serialized](Self::to_bytes) statistics.
+    pub const SIZE: usize = 4 * (5 + CATEGORIES) + 8 * 2;
+
+    /// Empty statistics.
+    pub const fn new() -> Self {
+        Self {
+            boots: 0,
+            power_ons: 0,
+            successful: 0,
+            failed: [0; CATEGORIES],
+            wifi_failures: 0,
+            ota_attempts: 0,
+            awake_ms: 0,
+            last_power_on: 0,
+        }
+    }
+
+    /// Serialize the statistics for storage, as little-endian integers in the order of the fields.
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        let words = [self.boots, self.power_ons, self.successful]
+            .into_iter()
+            .chain(self.failed)
+            .chain([self.wifi_failures, self.ota_attempts]);
+        let mut chunks = bytes.chunks_exact_mut(4);
+
+        for (chunk, word) in chunks.by_ref().zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+
+        let rest = chunks.into_remainder();
+        rest[..8].copy_from_slice(&self.awake_ms.to_le_bytes());
+        rest[8..].copy_from_slice(&self.last_power_on.to_le_bytes());
+
+        bytes
+    }
+
+    /// Deserialize statistics serialized using [`to_bytes()`](Self::to_bytes).
+    ///
+    /// Returns [`Option::None`] if the length does not match, e.g. after the layout has changed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+
+        let (words, rest) = bytes.split_at(4 * (5 + CATEGORIES));
+        let mut words = words
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap_or_default()));
+        let mut next = || words.next().unwrap_or_default();
+
+        Some(Self {
+            boots: next(),
+            power_ons: next(),
+            successful: next(),
+            failed: [(); CATEGORIES].map(|()| next()),
+            wifi_failures: next(),
+            ota_attempts: next(),
+            awake_ms: u64::from_le_bytes(rest[..8].try_into().ok()?),
+            last_power_on: u64::from_le_bytes(rest[8..].try_into().ok()?),
+        })
+    }
+
+    /// Returns the total number of failed wake cycles.
+    pub fn failed_total(self) -> u32 {
+        self.failed.iter().sum()
+    }
+
+    /// Format the statistics in a single line.
+    pub fn summary(self) -> String {
+        let failed = ErrorCategory::ALL
+            .iter()
+            .zip(self.failed)
+            .filter(|(_, count)| *count != 0)
+            .map(|(category, count)| format!("{}:{count}", category.name()))
+            .collect::<Vec<String>>();
+
+        let mut text = format!(
+            "boots={}, power-ons={}, ok={}, failed={}{}, wifi failures={}, OTA attempts={}, awake={:.02}h",
+            self.boots,
+            self.power_ons,
+            self.successful,
+            self.failed_total(),
+            if failed.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", failed.join("/"))
+            },
+            self.wifi_failures,
+            self.ota_attempts,
+            Duration::from_millis(self.awake_ms).as_secs_f32() / 3600.0
+        );
+
+        if self.last_power_on != 0 {
+            let _ = write!(text, ", power-on={}", self.last_power_on);
+        }
+
+        text
+    }
+}
+
+/// Count the boot caused by `reason`, restoring the statistics from NVS if RTC memory was lost.
+pub fn boot(nvs: &NonVolatileStorage, reason: ResetReason) {
+    let restored = current().is_none();
+
+    if restored {
+        let stats = nvs
+            .get_stats()
+            .inspect_err(|why| log::warn!("Failed to read statistics: {why}"))
+            .ok()
+            .flatten()
+            .unwrap_or(Stats::new());
+
+        STATS.update(|current| *current = Some(stats));
+    }
+
+    update(|stats| {
+        stats.boots = stats.boots.saturating_add(1);
+
+        if reason == ResetReason::PowerOn {
+            stats.power_ons = stats.power_ons.saturating_add(1);
+            stats.last_power_on = 0;
+        }
+    });
+
+    if restored {
+        checkpoint(nvs);
+    }
+}
+
+/// Count a wake cycle that failed with `error`.
+pub fn record_failure(error: &OsError) {
+    update(|stats| {
+        let count = &mut stats.failed[error.category().index()];
+        *count = count.saturating_add(1);
+    });
+}
+
+/// Count a wake cycle that finished without an error.
+pub fn record_success() {
+    update(|stats| stats.successful = stats.successful.saturating_add(1));
+}
+
+/// Count a failed `WiFi` connection attempt.
+pub fn record_wifi_failure() {
+    update(|stats| stats.wifi_failures = stats.wifi_failures.saturating_add(1));
+}
+
+/// Count a firmware update attempt.
+pub fn record_ota_attempt() {
+    update(|stats| stats.ota_attempts = stats.ota_attempts.saturating_add(1));
+}
+
+/// Record the end of the wake cycle after being `awake` for the given time, and checkpoint if it's due.
+pub fn finish(nvs: &NonVolatileStorage, awake: Duration) {
+    let awake_ms = u64::try_from(awake.as_millis()).unwrap_or(u64::MAX);
+
+    update(|stats| {
+        stats.awake_ms = stats.awake_ms.saturating_add(awake_ms);
+
+        if stats.last_power_on == 0 && clock::is_synced() {
+            stats.last_power_on = now_us().saturating_sub(rtc_us()) / 1_000_000;
+        }
+    });
+
+    if schedule::every(STATS_CHECKPOINT_INTERVAL) {
+        checkpoint(nvs);
+    }
+}
+
+/// Send the statistics to the server, if it's due in this cycle.
+pub fn report_if_due(pws: &mut PwmpClient) {
+    let Some(stats) = current() else {
+        return;
+    };
+
+    if !schedule::every(STATS_REPORT_INTERVAL) {
+        return;
+    }
+
+    log::info!("Reporting statistics");
+    if let Err(why) = pws.send_notification(format!(
+        "Stats: {}, up={:.02}h",
+        stats.summary(),
+        Duration::from_micros(rtc_us()).as_secs_f32() / 3600.0
+    )) {
+        log::warn!("Failed to report statistics: {why}");
+    }
+}
+
+/// Returns the statistics of the current run, `None` if they were not loaded yet.
+pub fn current() -> Option<Stats> {
+    STATS.get()
+}
+
+/// Clear the statistics, both in RTC memory and in NVS.
+///
+/// This is only called by the console.
+pub fn reset(nvs: &NonVolatileStorage) {
+    STATS.update(|stats| *stats = Some(Stats::new()));
+    checkpoint(nvs);
+}
+
+/// Write the statistics to NVS.
+fn checkpoint(nvs: &NonVolatileStorage) {
+    if let Some(stats) = current() {
+        nvs.store_stats(stats).report("Failed to store statistics");
+    }
+}
+
+/// Modify the statistics, if they are loaded.
+fn update(f: impl FnOnce(&mut Stats)) {
+    STATS.update(|stats| {
+        if let Some(stats) = stats {
+            f(stats);
+        }
+    });
+}
@@ -0,0 +1,167 @@
+//! Supervision of the wake cycle, which guarantees that the node goes back to sleep.
+//!
+//! A hang in the radio driver, on the I2C bus or in the PWMP communication would otherwise keep the node awake
+//! until the battery is drained.
+//!
+//! ## How it works
+//! - While the firmware runs, a timer checks the running [phase](super::phases) every [`CHECK_INTERVAL`].
+//! - If the phase has exceeded its budget, or the whole run has exceeded [`RUN_DEADLINE`], the node is put to sleep
+//!   right away. Firmware updates are only limited by their own budget.
+//! - The node wakes up when the run would have, had it ended when the supervisor was started. This sleep time
+//!   (adjusted for the [power tier](super::battery::policy) and [safe mode](super::safe_mode)) is computed
+//!   by the main task up front, since the timer must not access its state. All [wake-up sources](super::wake)
+//!   are enabled, the same way as at the end of a regular run.
+//! - The phase that overran and the time spent awake are kept in RTC memory. After the next boot, the overrun is
+//!   stored in NVS as the last error (so that it's reported to the server), and the run is recorded
+//!   in the [statistics](super::stats), [phases](super::phases) and [boot history](super::safe_mode).
+
+use super::{
+    clock::MIN_SLEEP_TIME,
+    phases::{self, Phase},
+    power::mcu_sleep,
+    schedule::rtc_us,
+    wake::WakeSources,
+    OsError, OsResult,
+};
+use crate::{
+    config::{RUN_DEADLINE, WIFI_TIMEOUT},
+    re_esp,
+};
+use esp_idf_svc::timer::{EspTaskTimerService, EspTimer};
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicU8, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+/// Interval of checking the running phase.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Marks that the previous run did not overrun.
+const NO_OVERRUN: u8 = u8::MAX;
+/// Marks that the previous run has exceeded the deadline, while no phase was running.
+const DEADLINE_OVERRUN: u8 = u8::MAX - 1;
+
+/// Index of the phase that has overrun in the previous run, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static OVERRUN: AtomicU8 = AtomicU8::new(NO_OVERRUN);
+
+/// Time spent awake during the previous run until the overrun in milliseconds, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static OVERRUN_AWAKE_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Supervisor handle, the supervision is stopped when this is dropped.
+pub struct Supervisor(EspTimer<'static>);
+
+/// An overrun of the previous run.
+pub struct Overrun {
+    /// Error caused by the overrun
+    pub error: OsError,
+
+    /// Time spent awake until the overrun
+    pub awake: Duration,
+}
+
+impl Supervisor {
+    /// Start supervising the run, that has started at `boot`.
+    ///
+    /// On an overrun, the node is put to sleep using the `wake_sources`, until `sleep_time` from now.
+    ///
+    /// # Errors
+    /// Returns an error if the timer cannot be started.
+    pub fn start(
+        boot: Instant,
+        sleep_time: Duration,
+        wake_sources: Arc<Mutex<WakeSources>>,
+    ) -> OsResult<Self> {
+        let start = Instant::now();
+        let sleep_us = u64::try_from(sleep_time.as_micros()).unwrap_or(u64::MAX);
+        let wake_at_us = rtc_us().saturating_add(sleep_us);
+
+        wake_sources
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .prepare(sleep_time);
+
+        let service = re_esp!(EspTaskTimerService::new(), TimerInit)?;
+        let timer = re_esp!(
+            service.timer(move || check(start.elapsed(), boot, wake_at_us, &wake_sources)),
+            TimerInit
+        )?;
+        re_esp!(timer.every(CHECK_INTERVAL), TimerInit)?;
+
+        Ok(Self(timer))
+    }
+}
+
+/// Returns the overrun during the previous run, and clears it.
+pub fn take_overrun() -> Option<Overrun> {
+    let error = match OVERRUN.swap(NO_OVERRUN, Ordering::Relaxed) {
+        NO_OVERRUN => None,
+        DEADLINE_OVERRUN => Some(OsError::RunTimeout),
+        index => Phase::ALL
+            .get(usize::from(index))
+            .map(|phase| OsError::PhaseTimeout(phase.name())),
+    }?;
+
+    Some(Overrun {
+        error,
+        awake: Duration::from_millis(u64::from(OVERRUN_AWAKE_MS.load(Ordering::Relaxed))),
+    })
+}
+
+/// Time budget of a phase, `None` if the phase is not supervised.
+pub const fn budget(phase: Phase) -> Option<Duration> {
+    match phase {
+        Phase::Boot | Phase::PeripheralInit => None,
+        Phase::WifiScan | Phase::Settings => Some(Duration::from_secs(10)),
+        // the driver should time out on its own
+        Phase::WifiConnect | Phase::Dhcp => {
+            Some(WIFI_TIMEOUT.saturating_add(Duration::from_secs(5)))
+        }
+        Phase::Handshake | Phase::OtaCheck => Some(Duration::from_secs(15)),
+        // particulate matter sensors need to warm up
+        Phase::SensorRead => Some(Duration::from_mins(1)),
+        Phase::Post => Some(Duration::from_secs(30)),
+        Phase::Update => Some(Duration::from_mins(10)),
+    }
+}
+
+/// Check whether the running phase or the whole run has overrun, and put the node to sleep until `wake_at_us` if so.
+///
+/// This runs on the timer task, so it must only use atomics and the values passed to it.
+fn check(elapsed: Duration, boot: Instant, wake_at_us: u64, wake_sources: &Mutex<WakeSources>) {
+    let overrun = match phases::active() {
+        Some((phase, running)) if budget(phase).is_some_and(|budget| running > budget) => {
+            log::error!("Phase `{}` has exceeded its budget", phase.name());
+            phase.index()
+        }
+        Some((Phase::Update, _)) => return,
+        Some((phase, _)) if elapsed > RUN_DEADLINE => {
+            log::error!("Run has exceeded its deadline in phase `{}`", phase.name());
+            phase.index()
+        }
+        None if elapsed > RUN_DEADLINE => {
+            log::error!("Run has exceeded its deadline");
+            usize::from(DEADLINE_OVERRUN)
+        }
+        _ => return,
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    OVERRUN.store(overrun as u8, Ordering::Relaxed);
+    OVERRUN_AWAKE_MS.store(
+        u32::try_from(boot.elapsed().as_millis()).unwrap_or(u32::MAX),
+        Ordering::Relaxed,
+    );
+    phases::cut_short();
+
+    let sleep_time = Duration::from_micros(wake_at_us.saturating_sub(rtc_us())).max(MIN_SLEEP_TIME);
+
+    // the main task only locks the wake-up sources before and after the supervision
+    if let Ok(wake_sources) = wake_sources.try_lock() {
+        wake_sources.arm();
+    }
+    mcu_sleep(Some(sleep_time));
+}
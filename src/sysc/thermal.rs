@@ -0,0 +1,269 @@
+//! Thermal protection of the battery and the MCU.
+//!
+//! ## How it works
+//! - The ambient temperature (measured by the environment sensor) is used as the temperature of the battery.
+//! - Outside of `CHARGE_TEMP_RANGE`, charging is disabled using the `CE` input of the solar charger,
+//!   since Li-ion cells must not be charged below 0°C and age quickly when charged hot. This requires the `solar`
+//!   feature, other boards have no way to stop a charger, so they don't have a charge limit at all.
+//! - Outside of [`OPERATING_TEMP_RANGE`], or if the CPU die is hotter than [`CPU_TEMP_LIMIT`], the radio is not started
+//!   at all and the node sleeps [`THERMAL_SLEEP_MULTIPLIER`] times longer. Firmware updates are skipped as well.
+//! - A limit is only considered to be met again, once the temperature is [`THERMAL_HYSTERESIS`] within it.
+//! - Excursions outside the limits are tracked in RTC memory. They are reported to the server when they start
+//!   (if the radio can be used), and once they have ended.
+
+use super::rtc::RtcCell;
+#[cfg(feature = "solar")]
+use crate::config::CHARGE_TEMP_RANGE;
+use crate::config::{
+    CPU_TEMP_LIMIT, OPERATING_TEMP_RANGE, THERMAL_HYSTERESIS, THERMAL_SLEEP_MULTIPLIER,
+};
+use std::{
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+/// Status selected during the last update, kept in RTC memory.
+///
+/// This is separate from [`STATE`], since it's also read when going to sleep from the supervisor's timer.
+#[link_section = ".rtc.data"]
+static STATUS: AtomicU8 = AtomicU8::new(ThermalStatus::NORMAL.to_bits());
+
+/// Thermal state, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static STATE: RtcCell<State> = RtcCell::new(State::new());
+
+/// What the node may do at the current temperature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThermalStatus {
+    /// Whether the battery may be charged, always `true` without the `solar` feature
+    pub charging: bool,
+
+    /// Whether the radio and other heavy work may be used
+    pub operating: bool,
+}
+
+/// A period, during which the temperature was outside the limits.
+#[derive(Debug, Clone, Copy)]
+pub struct Excursion {
+    /// Number of wake cycles
+    pub cycles: u32,
+
+    /// Lowest ambient temperature in °C
+    pub min: f32,
+
+    /// Highest ambient temperature in °C
+    pub max: f32,
+
+    /// Highest CPU die temperature in °C
+    pub cpu_max: f32,
+
+    /// Whether the node has stopped operating during the excursion
+    pub suspended: bool,
+
+    /// Whether charging was disabled through the charger's `CE` input during the excursion
+    pub charging_disabled: bool,
+}
+
+/// Thermal state.
+#[derive(Clone, Copy)]
+struct State {
+    /// The ongoing excursion.
+    excursion: Option<Excursion>,
+
+    /// Whether the start of the ongoing excursion was reported.
+    start_reported: bool,
+
+    /// Excursion that has ended, but was not reported yet.
+    ended: Option<Excursion>,
+}
+
+impl ThermalStatus {
+    /// Status within all limits.
+    pub const NORMAL: Self = Self {
+        charging: true,
+        operating: true,
+    };
+
+    /// Returns whether all limits are met.
+    pub const fn is_normal(self) -> bool {
+        self.charging && self.operating
+    }
+
+    /// Pack the status into a single integer for storage.
+    const fn to_bits(self) -> u8 {
+        (self.charging as u8) << 1 | self.operating as u8
+    }
+
+    /// Unpack a status packed using [`to_bits()`](Self::to_bits).
+    const fn from_bits(bits: u8) -> Self {
+        Self {
+            charging: bits & 0b10 != 0,
+            operating: bits & 0b01 != 0,
+        }
+    }
+
+    /// Returns the sleep time adjusted for the status.
+    pub fn sleep_time(self, sleep_time: Duration) -> Duration {
+        if self.operating {
+            sleep_time
+        } else {
+            sleep_time * THERMAL_SLEEP_MULTIPLIER
+        }
+    }
+}
+
+/// Select the status for the `ambient` and `cpu` temperatures in °C, and track excursions.
+pub fn update(ambient: f32, cpu: f32) -> ThermalStatus {
+    let status = next_status(current(), ambient, cpu);
+    STATUS.store(status.to_bits(), Ordering::Relaxed);
+
+    STATE.update(|state| {
+        if status.is_normal() {
+            if let Some(excursion) = state.excursion.take() {
+                state.ended = Some(excursion);
+            }
+            return;
+        }
+
+        if state.excursion.is_none() {
+            state.start_reported = false;
+        }
+
+        let excursion = state.excursion.get_or_insert(Excursion {
+            cycles: 0,
+            min: ambient,
+            max: ambient,
+            cpu_max: cpu,
+            suspended: false,
+            charging_disabled: false,
+        });
+
+        excursion.cycles += 1;
+        excursion.min = excursion.min.min(ambient);
+        excursion.max = excursion.max.max(ambient);
+        excursion.cpu_max = excursion.cpu_max.max(cpu);
+        excursion.suspended |= !status.operating;
+    });
+
+    if !status.is_normal() {
+        log::warn!(
+            "Temperature outside safe limits ({ambient:.01}*C, CPU {cpu:.01}*C): {status:?}"
+        );
+    }
+
+    status
+}
+
+/// Returns whether the battery may be charged, as selected during the last [`update()`].
+#[cfg(feature = "solar")]
+pub fn charging_allowed() -> bool {
+    current().charging
+}
+
+/// Record that charging was disabled through the charger during the ongoing excursion.
+#[cfg(feature = "solar")]
+pub fn charging_disabled() {
+    STATE.update(|state| {
+        if let Some(excursion) = &mut state.excursion {
+            excursion.charging_disabled = true;
+        }
+    });
+}
+
+/// Returns the notification about an excursion, if there is one that was not sent yet.
+pub fn pending_notification() -> Option<String> {
+    let state = STATE.get();
+
+    if let Some(excursion) = state.ended {
+        return Some(format!(
+            "Temperature is back within safe limits after {} wake-ups ({:.01}*C to {:.01}*C, CPU up to {:.01}*C){}",
+            excursion.cycles,
+            excursion.min,
+            excursion.max,
+            excursion.cpu_max,
+            if excursion.suspended {
+                ", the node was suspended"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    state
+        .excursion
+        .filter(|_| !state.start_reported)
+        .map(|excursion| {
+            format!(
+                "Temperature outside safe limits ({:.01}*C to {:.01}*C){}",
+                excursion.min,
+                excursion.max,
+                if excursion.charging_disabled {
+                    ", charging is disabled"
+                } else {
+                    ""
+                }
+            )
+        })
+}
+
+/// Mark the pending notification as sent.
+pub fn notification_sent() {
+    STATE.update(|state| {
+        if state.ended.take().is_none() {
+            state.start_reported = true;
+        }
+    });
+}
+
+/// Returns the status selected during the last [`update()`].
+fn current() -> ThermalStatus {
+    ThermalStatus::from_bits(STATUS.load(Ordering::Relaxed))
+}
+
+/// Select the status for the `ambient` and `cpu` temperatures, given the `current` one.
+pub fn next_status(current: ThermalStatus, ambient: f32, cpu: f32) -> ThermalStatus {
+    let cpu_ok = within(
+        cpu,
+        (f32::MIN, CPU_TEMP_LIMIT),
+        current.operating,
+        THERMAL_HYSTERESIS,
+    );
+
+    ThermalStatus {
+        #[cfg(feature = "solar")]
+        charging: within(
+            ambient,
+            CHARGE_TEMP_RANGE,
+            current.charging,
+            THERMAL_HYSTERESIS,
+        ),
+        #[cfg(not(feature = "solar"))]
+        charging: true,
+        operating: cpu_ok
+            && within(
+                ambient,
+                OPERATING_TEMP_RANGE,
+                current.operating,
+                THERMAL_HYSTERESIS,
+            ),
+    }
+}
+
+/// Returns whether `temp` is within the `(low, high)` range.
+///
+/// If it was not `inside` before, it must be at least `hysteresis` within the range.
+pub fn within(temp: f32, (low, high): (f32, f32), inside: bool, hysteresis: f32) -> bool {
+    let margin = if inside { 0.0 } else { hysteresis };
+
+    temp >= low + margin && temp <= high - margin
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            excursion: None,
+            start_reported: false,
+            ended: None,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Wake-up sources.
+//!
+//! Besides the sleep timer, the wake-up button and optional peripherals can wake the node up early
+//! (e.g. pulse inputs or sensor interrupts).
+//! Wake-up sources are not kept during deep sleep, so they must all be enabled again every time before going to sleep.
+
+#[cfg(feature = "as3935")]
+use super::lightning::LightningIrq;
+#[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+use super::pulse::PulseCounter;
+use super::{button, power::mcu_sleep, schedule, ReportableError};
+use std::time::Duration;
+
+/// All wake-up sources besides the sleep timer.
+pub struct WakeSources {
+    /// Anemometer and rain gauge inputs
+    #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+    pub pulse_counter: PulseCounter,
+
+    /// Lightning sensor interrupt
+    #[cfg(feature = "as3935")]
+    pub lightning: LightningIrq,
+}
+
+impl WakeSources {
+    /// Enable all wake-up sources and put the node to sleep for `time`.
+    pub fn sleep(&self, time: Duration) -> ! {
+        self.prepare(time);
+        self.arm();
+
+        log::debug!("Sleeping for {time:?}");
+        mcu_sleep(Some(time));
+    }
+
+    /// Remember when the node will wake up, if it goes to sleep for `time` now, and update the wake stub.
+    ///
+    /// This modifies the state in RTC memory, so it must only be called by the main task.
+    #[cfg_attr(
+        not(any(feature = "anemometer", feature = "rain-gauge")),
+        allow(clippy::unused_self)
+    )]
+    pub fn prepare(&self, time: Duration) {
+        schedule::set_next_wake(time);
+
+        #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+        self.pulse_counter.configure_stub();
+    }
+
+    /// Enable all wake-up sources for the next deep sleep.
+    ///
+    /// This only configures the hardware, so it can be called from any task, after [`prepare()`](Self::prepare).
+    #[cfg_attr(
+        not(any(feature = "anemometer", feature = "rain-gauge", feature = "as3935")),
+        allow(clippy::unused_self)
+    )]
+    pub fn arm(&self) {
+        button::arm().report("Failed to arm wake-up button");
+
+        #[cfg(any(feature = "anemometer", feature = "rain-gauge"))]
+        self.pulse_counter
+            .arm()
+            .report("Failed to arm pulse inputs");
+
+        #[cfg(feature = "as3935")]
+        self.lightning
+            .arm()
+            .report("Failed to arm lightning sensor interrupt");
+    }
+
+    /// Put the node back to sleep, if it has been woken up early.
+    ///
+    /// Returns if the regular wake-up is due.
+    #[cfg(any(feature = "anemometer", feature = "rain-gauge", feature = "as3935"))]
+    pub fn resume_sleep(&self) {
+        if let Some(remaining) = schedule::remaining_sleep() {
+            log::debug!("Woken up early, going back to sleep");
+            self.sleep(remaining);
+        }
+    }
+}
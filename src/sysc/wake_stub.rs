@@ -0,0 +1,265 @@
+//! Deep sleep wake stub, that records pulses without a full boot.
+//!
+//! ## How it works
+//! - The wake stub runs from RTC fast memory right after a wake-up, before the ESP-IDF or any peripheral is initialized.
+//! - On a pulse wake-up, it records the pulse in RTC memory, waits for the switch to open and goes straight back to sleep.
+//!   The sleep timer keeps its target, so the regular wake-up is not moved.
+//! - On any other wake-up, it continues to the full boot. This also happens if the input that has woken the MCU up
+//!   cannot be determined, or if the switch is being held closed. Such pulses are handled by the [`PulseCounter`](super::pulse::PulseCounter).
+//!
+//! The stub cannot use anything stored in flash (including the ESP-IDF and the standard library), so it only calls
+//! ROM functions and accesses the registers of the ESP32-S3 directly. Everything else it uses must be inlined.
+//! Pulses are timed using the RTC timer in slow clock ticks, which are converted by the full firmware.
+//! For the same reason, nothing in the stub may panic or check preconditions: arrays are indexed using raw pointers
+//! and counters wrap around. Without optimizations, even inlined functions are called, so the stub requires
+//! an optimized build (enforced by `build.rs`).
+//!
+//! The stub only handles pulse inputs, so it's only built with the `anemometer` or `rain-gauge` feature.
+//! Timer wake-ups always continue to the full boot, since every regular wake cycle transmits.
+
+use super::{pulse::INPUTS, rtc::RtcCell};
+use esp_idf_svc::sys::{
+    esp_clk_slowclk_cal_get, esp_default_wake_deep_sleep, esp_rom_delay_us,
+    esp_wake_stub_get_wakeup_cause, esp_wake_stub_sleep,
+};
+use std::{
+    ptr::{read_volatile, write_volatile},
+    time::Duration,
+};
+
+/// Requests an update of the RTC timer value registers.
+const RTC_CNTL_TIME_UPDATE_REG: usize = 0x6000_800C;
+/// Bit of [`RTC_CNTL_TIME_UPDATE_REG`], that triggers the update.
+const RTC_CNTL_TIME_UPDATE: u32 = 1 << 31;
+/// Lower 32 bits of the RTC timer.
+const RTC_CNTL_TIME_LOW0_REG: usize = 0x6000_8010;
+/// Upper 16 bits of the RTC timer.
+const RTC_CNTL_TIME_HIGH0_REG: usize = 0x6000_8014;
+/// Input levels of the RTC GPIOs.
+const RTC_GPIO_IN_REG: usize = 0x6000_8424;
+/// Offset of the first RTC GPIO in [`RTC_GPIO_IN_REG`].
+const RTC_GPIO_IN_SHIFT: u32 = 10;
+/// Wake-up cause bit of *ext1* wake-ups.
+const RTC_EXT1_TRIG_EN: u32 = 1 << 1;
+/// Number of fractional bits of the slow clock calibration value.
+const RTC_CLK_CAL_FRACT: u32 = 19;
+/// Interval of checking whether a switch has opened.
+const RELEASE_POLL_US: u32 = 1000;
+/// How many times to check whether a switch has opened, before leaving it to the full firmware.
+const RELEASE_POLLS: u32 = 50;
+
+/// State of the wake stub, kept in RTC memory.
+#[link_section = ".rtc.data"]
+static STATE: RtcCell<State> = RtcCell::new(State::new());
+
+/// Pulses recorded by the wake stub on a single input.
+#[derive(Clone, Copy)]
+struct StubInput {
+    /// Bit of the input in the RTC GPIO input levels, or `0` if the stub does not handle the input.
+    mask: u32,
+
+    /// Minimum time between two pulses in slow clock ticks.
+    debounce_ticks: u64,
+
+    /// Number of pulses since the last report.
+    count: u32,
+
+    /// RTC timer value of the last pulse.
+    last_ticks: Option<u64>,
+
+    /// Shortest interval between two pulses since the last report in slow clock ticks.
+    min_interval_ticks: Option<u64>,
+}
+
+/// Wake stub state.
+#[derive(Clone, Copy)]
+struct State {
+    /// Inputs, indexed the same way as the counters of the [`PulseCounter`](super::pulse::PulseCounter).
+    inputs: [StubInput; INPUTS],
+
+    /// Number of wake-ups handled by the stub since the last full boot.
+    wakes: u32,
+
+    /// Slow clock calibration value of the last full boot.
+    cal: u32,
+}
+
+/// Wake stub, called by the ROM bootloader after every wake-up from deep sleep.
+///
+/// # Safety
+/// This must only be called by the ROM bootloader.
+#[no_mangle]
+#[link_section = ".rtc.text"]
+pub unsafe extern "C" fn esp_wake_deep_sleep() {
+    esp_default_wake_deep_sleep();
+
+    if esp_wake_stub_get_wakeup_cause() & RTC_EXT1_TRIG_EN == 0 {
+        return;
+    }
+
+    let state = &mut *STATE.as_ptr();
+    let inputs = state.inputs.as_mut_ptr();
+    let low = !(read_volatile(RTC_GPIO_IN_REG as *const u32) >> RTC_GPIO_IN_SHIFT);
+
+    // Indexing or `get_unchecked()` would add a panic path in flash, `add()` checks its precondition in debug builds.
+    // The offsets are checked against `INPUTS` instead.
+
+    // the input that has woken the MCU up must be the only one being held low
+    let mut slot = INPUTS;
+    let mut i = 0;
+    while i < INPUTS {
+        if (*inputs.wrapping_add(i)).mask & low != 0 {
+            if slot != INPUTS {
+                return;
+            }
+            slot = i;
+        }
+        i += 1;
+    }
+
+    if slot == INPUTS {
+        return;
+    }
+
+    let input = &mut *inputs.wrapping_add(slot);
+
+    // the switch would wake the MCU up again right away, if it's still closed
+    let mut polls = 0;
+    while (read_volatile(RTC_GPIO_IN_REG as *const u32) >> RTC_GPIO_IN_SHIFT) & input.mask == 0 {
+        if polls == RELEASE_POLLS {
+            return;
+        }
+
+        esp_rom_delay_us(RELEASE_POLL_US);
+        polls = polls.wrapping_add(1);
+    }
+
+    let now = rtc_ticks();
+    let bounce = match input.last_ticks {
+        Some(last) => now.wrapping_sub(last) < input.debounce_ticks,
+        None => false,
+    };
+
+    if !bounce {
+        if let Some(last) = input.last_ticks {
+            let interval = now.wrapping_sub(last);
+            input.min_interval_ticks = match input.min_interval_ticks {
+                Some(min) if min < interval => Some(min),
+                _ => Some(interval),
+            };
+        }
+
+        input.count = input.count.wrapping_add(1);
+        input.last_ticks = Some(now);
+    }
+
+    state.wakes = state.wakes.wrapping_add(1);
+    esp_wake_stub_sleep(Some(esp_wake_deep_sleep));
+}
+
+/// Let the wake stub handle the input in the given `slot`, connected to the RTC GPIO `pin`.
+///
+/// This must be called before every deep sleep, since the slow clock calibration may change.
+pub fn configure(slot: usize, pin: i32, debounce: Duration) {
+    // SAFETY: Calling a safe C function.
+    let cal = unsafe { esp_clk_slowclk_cal_get() };
+
+    STATE.update(|state| {
+        state.cal = cal;
+        state.inputs[slot].mask = 1 << pin;
+        state.inputs[slot].debounce_ticks = us_to_ticks(debounce.as_micros(), cal);
+    });
+}
+
+/// Returns the number of pulses recorded by the wake stub on the input in the given `slot`,
+/// and the shortest interval between two of them.
+pub fn pulses(slot: usize) -> (u32, Option<Duration>) {
+    let state = STATE.get();
+    let input = state.inputs[slot];
+
+    (
+        input.count,
+        input
+            .min_interval_ticks
+            .map(|ticks| Duration::from_micros(ticks_to_us(ticks, state.cal))),
+    )
+}
+
+/// Clear the pulses recorded by the wake stub.
+pub fn reset() {
+    STATE.update(|state| {
+        for input in &mut state.inputs {
+            input.count = 0;
+            input.last_ticks = None;
+            input.min_interval_ticks = None;
+        }
+    });
+}
+
+/// Returns the number of wake-ups handled by the wake stub since the last full boot, and clears it.
+pub fn take_wakes() -> u32 {
+    let wakes = STATE.get().wakes;
+    STATE.update(|state| state.wakes = 0);
+
+    wakes
+}
+
+/// Convert slow clock ticks to microseconds, using the calibration value `cal`.
+#[allow(clippy::cast_possible_truncation)]
+pub const fn ticks_to_us(ticks: u64, cal: u32) -> u64 {
+    ((ticks as u128 * cal as u128) >> RTC_CLK_CAL_FRACT) as u64
+}
+
+/// Convert microseconds to slow clock ticks, using the calibration value `cal`.
+#[allow(clippy::cast_possible_truncation)]
+pub const fn us_to_ticks(us: u128, cal: u32) -> u64 {
+    if cal == 0 {
+        return 0;
+    }
+
+    ((us << RTC_CLK_CAL_FRACT) / cal as u128) as u64
+}
+
+/// Read the RTC timer.
+// the wake stub cannot call functions in flash
+#[allow(clippy::inline_always)]
+#[inline(always)]
+#[link_section = ".rtc.text"]
+unsafe fn rtc_ticks() -> u64 {
+    let update = read_volatile(RTC_CNTL_TIME_UPDATE_REG as *const u32);
+    write_volatile(
+        RTC_CNTL_TIME_UPDATE_REG as *mut u32,
+        update | RTC_CNTL_TIME_UPDATE,
+    );
+
+    let low = read_volatile(RTC_CNTL_TIME_LOW0_REG as *const u32);
+    let high = read_volatile(RTC_CNTL_TIME_HIGH0_REG as *const u32);
+
+    // `From` is not guaranteed to be inlined
+    #[allow(clippy::cast_lossless)]
+    let ticks = ((high as u64) << 32) | low as u64;
+
+    ticks
+}
+
+impl StubInput {
+    const fn new() -> Self {
+        Self {
+            mask: 0,
+            debounce_ticks: 0,
+            count: 0,
+            last_ticks: None,
+            min_interval_ticks: None,
+        }
+    }
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            inputs: [StubInput::new(); INPUTS],
+            wakes: 0,
+            cal: 0,
+        }
+    }
+}